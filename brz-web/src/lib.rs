@@ -0,0 +1,298 @@
+//! Browser frontend
+//!
+//! `Editor` renders into an in-memory grid of cells, that JavaScript
+//! can draw however it likes (eg. into `<pre>` or `<canvas>`), and takes
+//! DOM key events.
+//!
+//! There's no file system in the browser, so files live in memory:
+//! JavaScript adds them with `add_file`, and reads back what the editor
+//! wrote with `file_text`. Same with the clipboard: `clipboard_text`
+//! and `set_clipboard_text` are for syncing it with the browser one.
+
+use libbrz::render::{default_color_map, Coord, Frame, FrameRenderer};
+use libbrz::{Key, State};
+use ropey::Rope;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+
+/// The in-memory files
+#[derive(Default)]
+struct FileStore {
+    texts: BTreeMap<PathBuf, Rope>,
+    /// In the order they were added, since the finder expects its list
+    /// to only grow at the end
+    order: Vec<PathBuf>,
+}
+
+impl FileStore {
+    fn insert(&mut self, path: PathBuf, text: Rope) {
+        if self.texts.insert(path.clone(), text).is_none() {
+            self.order.push(path);
+        }
+    }
+
+    fn get(&self, path: &Path) -> Option<&Rope> {
+        self.texts.get(path)
+    }
+
+    /// All the paths, after the first `start` ones
+    fn paths_after(&self, start: usize) -> Vec<PathBuf> {
+        self.order.iter().skip(start).cloned().collect()
+    }
+}
+
+type Files = Rc<RefCell<FileStore>>;
+
+/// Map a DOM `KeyboardEvent.key` (and its modifiers) to a `Key`
+///
+/// Returns `None` for keys that the editor doesn't handle (eg. `Shift`
+/// alone), which should be left to the browser.
+pub fn dom_key_to_brz(key: &str, ctrl: bool, alt: bool) -> Option<Key> {
+    Some(match key {
+        "Backspace" => Key::Backspace,
+        "ArrowLeft" => Key::Left,
+        "ArrowRight" => Key::Right,
+        "ArrowUp" => Key::Up,
+        "ArrowDown" => Key::Down,
+        "Home" => Key::Home,
+        "End" => Key::End,
+        "PageUp" => Key::PageUp,
+        "PageDown" => Key::PageDown,
+        "Delete" => Key::Delete,
+        "Insert" => Key::Insert,
+        "Escape" => Key::Esc,
+        "Enter" => Key::Char('\n'),
+        "Tab" => Key::Char('\t'),
+        _ => {
+            if let Some(n) = key
+                .strip_prefix('F')
+                .and_then(|n| n.parse::<u8>().ok())
+                .filter(|n| 1 <= *n && *n <= 12)
+            {
+                return Some(Key::F(n));
+            }
+
+            let mut chars = key.chars();
+            let ch = match (chars.next(), chars.next()) {
+                (Some(ch), None) => ch,
+                _ => return None,
+            };
+            // same as terminals report them
+            if ctrl {
+                Key::Ctrl(ch.to_ascii_lowercase())
+            } else if alt {
+                Key::Alt(ch)
+            } else {
+                Key::Char(ch)
+            }
+        }
+    })
+}
+
+#[wasm_bindgen]
+pub struct Editor {
+    state: State,
+    files: Files,
+    clipboard: Rc<RefCell<String>>,
+    frame: Frame,
+}
+
+#[wasm_bindgen]
+impl Editor {
+    #[wasm_bindgen(constructor)]
+    pub fn new(cols: usize, rows: usize) -> Editor {
+        let files: Files = Rc::new(RefCell::new(FileStore::default()));
+        let mut state = State::default();
+
+        {
+            let files = files.clone();
+            state.register_read_handler(move |path| {
+                files
+                    .borrow()
+                    .get(path)
+                    .cloned()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "file not found"))
+            });
+        }
+        {
+            let files = files.clone();
+            state.register_write_handler(move |path, rope| {
+                files.borrow_mut().insert(path.to_owned(), rope.clone());
+                Ok(())
+            });
+        }
+        {
+            let files = files.clone();
+            state.register_find_handler(move |start| Ok(files.borrow().paths_after(start)));
+        }
+        let clipboard = Rc::new(RefCell::new(String::new()));
+        {
+            let (copy, paste) = (clipboard.clone(), clipboard.clone());
+            state.register_clipboard_handler(
+                move |text| {
+                    *copy.borrow_mut() = text.to_owned();
+                    Ok(())
+                },
+                move || Ok(paste.borrow().clone()),
+            );
+        }
+
+        let mut editor = Editor {
+            state,
+            files,
+            clipboard,
+            frame: Frame::default(),
+        };
+        editor.resize(cols, rows);
+        editor
+    }
+
+    pub fn add_file(&mut self, path: &str, text: &str) {
+        self.files
+            .borrow_mut()
+            .insert(PathBuf::from(path), Rope::from_str(text));
+    }
+
+    pub fn file_text(&self, path: &str) -> Option<String> {
+        self.files
+            .borrow()
+            .get(Path::new(path))
+            .map(|rope| rope.to_string())
+    }
+
+    pub fn clipboard_text(&self) -> String {
+        self.clipboard.borrow().clone()
+    }
+
+    pub fn set_clipboard_text(&mut self, text: &str) {
+        *self.clipboard.borrow_mut() = text.to_owned();
+    }
+
+    pub fn open(&mut self, path: &str) {
+        self.state.open_buffer(&PathBuf::from(path));
+        self.render();
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.frame = Frame::new(Coord { x: cols, y: rows });
+        self.render();
+    }
+
+    /// Handle a DOM key event
+    ///
+    /// Returns `false` if the key was not for the editor, and the browser
+    /// should handle it instead.
+    pub fn handle_key(&mut self, key: &str, ctrl: bool, alt: bool) -> bool {
+        match dom_key_to_brz(key, ctrl, alt) {
+            Some(key) => {
+                self.state.handle_key(key);
+                self.render();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.is_finished()
+    }
+
+    fn render(&mut self) {
+        if self.frame.dimensions.x == 0 || self.frame.dimensions.y < 2 {
+            return;
+        }
+        let mut render = FrameRenderer::new(self.frame.dimensions, default_color_map());
+        self.state.render(&mut render);
+        self.frame = render.frame;
+    }
+
+    pub fn cols(&self) -> usize {
+        self.frame.dimensions.x
+    }
+
+    pub fn rows(&self) -> usize {
+        self.frame.dimensions.y
+    }
+
+    pub fn row_text(&self, y: usize) -> String {
+        if y < self.frame.dimensions.y {
+            self.frame.row_text(y)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Unicode code point of the char in the cell
+    pub fn cell_char(&self, x: usize, y: usize) -> u32 {
+        self.frame
+            .get(Coord { x, y })
+            .map(|(ch, _)| ch as u32)
+            .unwrap_or(' ' as u32)
+    }
+
+    /// ANSI 256 color of the cell foreground, or `-1` for the default one
+    pub fn cell_fg(&self, x: usize, y: usize) -> i32 {
+        self.frame
+            .get(Coord { x, y })
+            .and_then(|(_, style)| style.fg)
+            .map(|c| c as i32)
+            .unwrap_or(-1)
+    }
+
+    /// ANSI 256 color of the cell background, or `-1` for the default one
+    pub fn cell_bg(&self, x: usize, y: usize) -> i32 {
+        self.frame
+            .get(Coord { x, y })
+            .and_then(|(_, style)| style.bg)
+            .map(|c| c as i32)
+            .unwrap_or(-1)
+    }
+
+    /// Style bits of the cell (`1` is bold)
+    pub fn cell_style(&self, x: usize, y: usize) -> u32 {
+        self.frame
+            .get(Coord { x, y })
+            .and_then(|(_, style)| style.style)
+            .unwrap_or(0)
+    }
+
+    /// Cursor column, or `-1` if the cursor is hidden
+    pub fn cursor_x(&self) -> i32 {
+        self.frame.cursor.map(|c| c.x as i32).unwrap_or(-1)
+    }
+
+    /// Cursor row, or `-1` if the cursor is hidden
+    pub fn cursor_y(&self) -> i32 {
+        self.frame.cursor.map(|c| c.y as i32).unwrap_or(-1)
+    }
+}
+
+#[test]
+fn dom_key_to_brz_test() {
+    assert_eq!(dom_key_to_brz("a", false, false), Some(Key::Char('a')));
+    assert_eq!(dom_key_to_brz("P", true, false), Some(Key::Ctrl('p')));
+    assert_eq!(dom_key_to_brz("S", false, true), Some(Key::Alt('S')));
+    assert_eq!(dom_key_to_brz("Enter", false, false), Some(Key::Char('\n')));
+    assert_eq!(dom_key_to_brz("F5", false, false), Some(Key::F(5)));
+    assert_eq!(dom_key_to_brz("F", false, false), Some(Key::Char('F')));
+    assert_eq!(dom_key_to_brz("Shift", false, false), None);
+}
+
+#[test]
+fn file_store_order_test() {
+    let mut files = FileStore::default();
+    files.insert(PathBuf::from("b.txt"), Rope::from_str("b"));
+    files.insert(PathBuf::from("a.txt"), Rope::from_str("a"));
+    // written again, so it stays where it was
+    files.insert(PathBuf::from("b.txt"), Rope::from_str("bb"));
+    assert_eq!(
+        files.paths_after(0),
+        vec![PathBuf::from("b.txt"), PathBuf::from("a.txt")]
+    );
+    assert_eq!(files.paths_after(1), vec![PathBuf::from("a.txt")]);
+    assert_eq!(files.get(Path::new("b.txt")).unwrap().to_string(), "bb");
+}
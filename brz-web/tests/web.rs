@@ -0,0 +1,62 @@
+//! Run with `wasm-pack test --node brz-web`
+#![cfg(target_arch = "wasm32")]
+
+use brz_web::Editor;
+use wasm_bindgen_test::*;
+
+fn keys(editor: &mut Editor, keys: &str) {
+    for ch in keys.chars() {
+        let key = match ch {
+            '\n' => "Enter".to_owned(),
+            '\x1b' => "Escape".to_owned(),
+            ch => ch.to_string(),
+        };
+        assert!(editor.handle_key(&key, false, false));
+    }
+}
+
+#[wasm_bindgen_test]
+fn edit_and_write_test() {
+    let mut editor = Editor::new(40, 10);
+    editor.add_file("hello.txt", "world\n");
+    editor.open("hello.txt");
+    assert!(editor.row_text(0).contains("world"));
+
+    keys(&mut editor, "ihello \x1b:w\n");
+    assert_eq!(
+        editor.file_text("hello.txt").as_ref().map(String::as_str),
+        Some("hello world\n")
+    );
+    assert!(editor.row_text(0).contains("hello world"));
+    assert!(editor.cursor_y() >= 0);
+
+    keys(&mut editor, ":q\n");
+    assert!(editor.is_finished());
+}
+
+#[wasm_bindgen_test]
+fn unhandled_keys_test() {
+    let mut editor = Editor::new(40, 10);
+    assert!(!editor.handle_key("Shift", false, false));
+    assert_eq!(editor.cols(), 40);
+    assert_eq!(editor.rows(), 10);
+}
+
+#[wasm_bindgen_test]
+fn clipboard_test() {
+    let mut editor = Editor::new(40, 10);
+    editor.add_file("hello.txt", "hello\n");
+    editor.open("hello.txt");
+
+    keys(&mut editor, "x");
+    assert!(editor.handle_key("y", false, true));
+    assert_eq!(editor.clipboard_text(), "hello\n");
+
+    editor.set_clipboard_text("bye");
+    assert!(editor.handle_key("p", false, true));
+    keys(&mut editor, ":w\n");
+    assert_eq!(
+        editor.file_text("hello.txt").as_ref().map(String::as_str),
+        Some("hello\nbye")
+    );
+}
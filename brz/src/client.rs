@@ -0,0 +1,107 @@
+//! Terminal attached to a session held by a daemon
+
+use libbrz::prelude::*;
+use libbrz::protocol::{self, ClientMessage, ServerMessage};
+use libbrz::render::Renderer;
+
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc;
+use std::thread;
+use termion::event::Event as TermEvent;
+use termion::input::TermRead;
+
+use crate::daemon::{check_socket_dir, socket_path};
+use crate::render::Render;
+
+enum Event {
+    Key(libbrz::Key),
+    Server(std::io::Result<ServerMessage>),
+}
+
+fn send(stream: &mut UnixStream, msg: &ClientMessage) -> Result<()> {
+    protocol::write_message(stream, &msg.encode())?;
+    Ok(())
+}
+
+fn recv(stream: &mut UnixStream) -> std::io::Result<ServerMessage> {
+    protocol::read_message(stream).and_then(|msg| ServerMessage::decode(&msg))
+}
+
+pub fn run(session: &str) -> Result<()> {
+    let path = socket_path(session);
+    check_socket_dir(&path)?;
+    let mut stream = UnixStream::connect(&path).map_err(|e| {
+        failure::format_err!(
+            "can't connect to session `{}` at {}: {}",
+            session,
+            path.display(),
+            e
+        )
+    })?;
+
+    send(
+        &mut stream,
+        &ClientMessage::Hello {
+            version: protocol::VERSION,
+        },
+    )?;
+    match recv(&mut stream)? {
+        ServerMessage::Welcome { .. } => {}
+        ServerMessage::Error(e) => failure::bail!("{}", e),
+        _ => failure::bail!("unexpected reply from the daemon"),
+    }
+
+    let mut render = Render::new()?;
+    let (tx, rx) = mpsc::channel();
+
+    {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            for e in std::io::stdin().events() {
+                if let Ok(TermEvent::Key(key)) = e {
+                    if let Some(key) = crate::termion_to_brz_key(key) {
+                        if tx.send(Event::Key(key)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    {
+        let mut stream = stream.try_clone()?;
+        thread::spawn(move || loop {
+            let msg = recv(&mut stream);
+            let failed = msg.is_err();
+            if tx.send(Event::Server(msg)).is_err() || failed {
+                return;
+            }
+        });
+    }
+
+    let mut dimensions = render.dimensions();
+    send(&mut stream, &ClientMessage::Resize(dimensions))?;
+
+    for event in rx {
+        match event {
+            Event::Key(key) => send(&mut stream, &ClientMessage::Key(key))?,
+            Event::Server(msg) => match msg? {
+                ServerMessage::Frame(frame) => {
+                    render.draw_frame(&frame)?;
+                    // terminal size is only checked after drawing
+                    if render.dimensions() != dimensions {
+                        dimensions = render.dimensions();
+                        send(&mut stream, &ClientMessage::Resize(dimensions))?;
+                    }
+                }
+                ServerMessage::Clipboard(text) => render.set_clipboard(&text)?,
+                ServerMessage::Quit => return Ok(()),
+                ServerMessage::Error(e) => failure::bail!("{}", e),
+                ServerMessage::Welcome { .. } => {}
+            },
+        }
+    }
+
+    Ok(())
+}
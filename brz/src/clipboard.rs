@@ -0,0 +1,134 @@
+//! System clipboard
+//!
+//! Copying uses the OSC 52 escape sequence, which the terminal handles
+//! itself, so it works over SSH and in tmux. It's written by whatever
+//! draws on the terminal (see `State::take_copied`), as the handlers
+//! don't know which one it is with a daemon. Terminals rarely let
+//! programs read the clipboard back that way, so pasting (and copying
+//! when not on a terminal) goes through `wl-copy`/`wl-paste` or `xclip`.
+
+use std::cell::RefCell;
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).cloned().unwrap_or(0),
+            chunk.get(2).cloned().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+/// Escape sequence setting the clipboard of the terminal to `text`
+pub fn osc52(text: &str) -> String {
+    let seq = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    if env::var_os("TMUX").is_some() {
+        // passed through to the terminal tmux runs in
+        format!("\x1bPtmux;{}\x1b\\", seq.replace('\x1b', "\x1b\x1b"))
+    } else {
+        seq
+    }
+}
+
+/// The clipboard commands of the running display server: `(copy, paste)`
+fn commands() -> Option<(&'static [&'static str], &'static [&'static str])> {
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        Some((&["wl-copy"], &["wl-paste", "--no-newline"]))
+    } else if env::var_os("DISPLAY").is_some() {
+        Some((
+            &["xclip", "-selection", "clipboard"],
+            &["xclip", "-selection", "clipboard", "-o"],
+        ))
+    } else {
+        None
+    }
+}
+
+fn command_failed(cmd: &str, status: std::process::ExitStatus) -> io::Error {
+    io::Error::other(format!("{}: {}", cmd, status))
+}
+
+fn copy_command(cmd: &[&str], text: &str) -> io::Result<()> {
+    let mut child = Command::new(cmd[0])
+        .args(&cmd[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin piped")
+        .write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(command_failed(cmd[0], status));
+    }
+    Ok(())
+}
+
+fn paste_command(cmd: &[&str]) -> io::Result<String> {
+    let output = Command::new(cmd[0])
+        .args(&cmd[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(command_failed(cmd[0], output.status));
+    }
+    String::from_utf8(output.stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// `(copy, paste)` handlers for `State::register_clipboard_handler`
+///
+/// With `on_terminal`, copying can't fail, as the terminal gets it
+/// with OSC 52 anyway. Without any clipboard command, pasting gives
+/// back what was copied last, so it still works within the editor.
+pub fn handlers(
+    on_terminal: bool,
+) -> (
+    impl Fn(&str) -> io::Result<()>,
+    impl Fn() -> io::Result<String>,
+) {
+    let last_copied = Rc::new(RefCell::new(String::new()));
+
+    let copied = last_copied.clone();
+    let copy = move |text: &str| {
+        *copied.borrow_mut() = text.to_owned();
+        match commands() {
+            Some((cmd, _)) => match copy_command(cmd, text) {
+                Err(_) if on_terminal => Ok(()),
+                res => res,
+            },
+            None if on_terminal => Ok(()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no terminal or clipboard command",
+            )),
+        }
+    };
+
+    let paste = move || match commands().map(|(_, cmd)| paste_command(cmd)) {
+        Some(Err(ref e)) if e.kind() == io::ErrorKind::NotFound => Ok(last_copied.borrow().clone()),
+        Some(res) => res,
+        None => Ok(last_copied.borrow().clone()),
+    };
+
+    (copy, paste)
+}
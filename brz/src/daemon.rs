@@ -0,0 +1,219 @@
+//! Serving one editor session to many clients over a Unix socket
+
+use libbrz::protocol::{self, ClientMessage};
+use libbrz::server::{ClientId, Server};
+use libbrz::{prelude::*, state::State};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::Shutdown;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use libbrz::render::default_color_map;
+
+fn uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+/// `$XDG_RUNTIME_DIR`, or our own directory in the shared `$TMPDIR`
+fn socket_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir().join(format!("brz-{}", uid())),
+    }
+}
+
+pub fn socket_path(session: &str) -> PathBuf {
+    socket_dir().join(format!("brz-{}.sock", session))
+}
+
+/// Create the directory of the sockets if needed, and make sure
+/// no other user can get into it
+///
+/// Anyone could have created it before us, so whatever is there
+/// is only used if we own it and it is closed to everyone else.
+pub fn check_socket_dir(socket_path: &Path) -> Result<()> {
+    let dir = socket_path.parent().expect("socket in a directory");
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        res => res?,
+    }
+
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid() {
+        failure::bail!("{} is not a directory of the current user", dir.display());
+    }
+    if metadata.mode() & 0o077 != 0 {
+        failure::bail!("{} is open to other users", dir.display());
+    }
+    Ok(())
+}
+
+/// User on the other end of `stream`
+#[cfg(target_os = "linux")]
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+/// User on the other end of `stream`
+#[cfg(not(target_os = "linux"))]
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let (mut uid, mut gid) = (0, 0);
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+/// How long a client can keep us blocked on a write
+///
+/// All clients are served from one thread, so one that stopped reading
+/// would stall everyone else; after this long it is dropped instead.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Every connection gets its own number, so that a stale event
+/// can't be mistaken for one of a new client reusing the `ClientId`
+type ConnectionId = u64;
+
+enum Event {
+    Connected(UnixStream),
+    Message(ConnectionId, ClientMessage),
+    Disconnected(ConnectionId),
+}
+
+struct Connection {
+    client_id: ClientId,
+    stream: UnixStream,
+}
+
+fn read_messages(conn_id: ConnectionId, mut stream: UnixStream, tx: mpsc::Sender<Event>) {
+    loop {
+        match protocol::read_message(&mut stream).and_then(|msg| ClientMessage::decode(&msg)) {
+            Ok(msg) => {
+                if tx.send(Event::Message(conn_id, msg)).is_err() {
+                    return;
+                }
+            }
+            Err(_) => {
+                let _ = tx.send(Event::Disconnected(conn_id));
+                return;
+            }
+        }
+    }
+}
+
+pub fn run(session: &str, state: State) -> Result<()> {
+    let path = socket_path(session);
+    check_socket_dir(&path)?;
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            failure::bail!("session `{}` is already running", session);
+        }
+        // left over after a daemon that didn't exit cleanly
+        std::fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    eprintln!("brz: serving session `{}` at {}", session, path.display());
+
+    let res = serve(listener, state);
+    let _ = std::fs::remove_file(&path);
+    res
+}
+
+fn serve(listener: UnixListener, state: State) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+
+    {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // the socket is private already, but just in case
+                if peer_uid(&stream).ok() != Some(uid()) {
+                    continue;
+                }
+                if tx.send(Event::Connected(stream)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    let mut server = Server::new(state, default_color_map());
+    let mut connections: HashMap<ConnectionId, Connection> = HashMap::new();
+    let mut next_conn_id: ConnectionId = 0;
+
+    for event in rx {
+        match event {
+            Event::Connected(stream) => {
+                let (writer, reader) = match (stream.try_clone(), stream.try_clone()) {
+                    (Ok(writer), Ok(reader)) => (writer, reader),
+                    _ => continue,
+                };
+                if writer.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+                    continue;
+                }
+                let conn_id = next_conn_id;
+                next_conn_id += 1;
+
+                let client_id = server.add_client(writer);
+                connections.insert(conn_id, Connection { client_id, stream });
+
+                let tx = tx.clone();
+                thread::spawn(move || read_messages(conn_id, reader, tx));
+            }
+            Event::Message(conn_id, msg) => {
+                if let Some(conn) = connections.get(&conn_id) {
+                    server.handle_message(conn.client_id, msg);
+                }
+            }
+            Event::Disconnected(conn_id) => {
+                if let Some(conn) = connections.remove(&conn_id) {
+                    server.remove_client(conn.client_id);
+                }
+            }
+        }
+
+        if server.is_finished() {
+            return Ok(());
+        }
+
+        // drop the connections the server gave up on
+        let dropped: Vec<_> = connections
+            .iter()
+            .filter(|(_, conn)| !server.has_client(conn.client_id))
+            .map(|(conn_id, _)| *conn_id)
+            .collect();
+        for conn_id in dropped {
+            if let Some(conn) = connections.remove(&conn_id) {
+                let _ = conn.stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    Ok(())
+}
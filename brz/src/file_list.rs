@@ -0,0 +1,55 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Files under the current directory, for the finder to pick from
+///
+/// Walking a big repository can take a while, so it's done in a background
+/// thread and `since` returns whatever was found so far. The list is only
+/// appended to, so callers can ask just for the files they haven't seen.
+pub struct FileList {
+    files: Arc<Mutex<Vec<PathBuf>>>,
+    done: Arc<AtomicBool>,
+}
+
+impl FileList {
+    pub fn walk_current_dir() -> Self {
+        let files = Arc::new(Mutex::new(vec![]));
+        let done = Arc::new(AtomicBool::new(false));
+
+        thread::spawn({
+            let files = files.clone();
+            let done = done.clone();
+            move || {
+                let mut batch = vec![];
+                for entry in ignore::Walk::new(".")
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.file_type().map(|f| f.is_file()).unwrap_or(false))
+                {
+                    let path = entry.into_path();
+                    batch.push(path.strip_prefix(".").map(Path::to_owned).unwrap_or(path));
+
+                    if batch.len() == 1024 {
+                        files.lock().expect("lock").append(&mut batch);
+                    }
+                }
+                files.lock().expect("lock").append(&mut batch);
+                done.store(true, Ordering::SeqCst);
+            }
+        });
+
+        Self { files, done }
+    }
+
+    /// Has the walk finished
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+
+    /// Files found so far, after the first `start` ones
+    pub fn since(&self, start: usize) -> Vec<PathBuf> {
+        let files = self.files.lock().expect("lock");
+        files.get(start..).map(<[_]>::to_vec).unwrap_or_default()
+    }
+}
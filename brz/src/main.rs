@@ -6,29 +6,164 @@ use termion::input::TermRead;
 
 use ropey::Rope;
 use std;
+use std::cell::RefCell;
+use std::io::{self, Read};
 use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 
+mod client;
+mod clipboard;
+mod daemon;
+mod file_list;
 mod opts;
 mod render;
+mod rpc;
 
+use self::file_list::FileList;
+
+use libbrz::headless::{parse_keys, Headless};
+use libbrz::plugin::Capability;
+use libbrz::render::Coord;
 use libbrz::{prelude::*, state::State};
 
-fn termion_to_brz_key(key: termion::event::Key) -> libbrz::Key {
-    match key {
+/// The `libbrz::Key` of a terminal key, if there's one
+fn termion_to_brz_key(key: termion::event::Key) -> Option<libbrz::Key> {
+    Some(match key {
         termion::event::Key::Backspace => libbrz::Key::Backspace,
         termion::event::Key::Left => libbrz::Key::Left,
         termion::event::Key::Up => libbrz::Key::Up,
         termion::event::Key::Right => libbrz::Key::Right,
         termion::event::Key::Down => libbrz::Key::Down,
         termion::event::Key::Home => libbrz::Key::Home,
+        termion::event::Key::End => libbrz::Key::End,
+        termion::event::Key::PageUp => libbrz::Key::PageUp,
+        termion::event::Key::PageDown => libbrz::Key::PageDown,
+        termion::event::Key::Delete => libbrz::Key::Delete,
+        termion::event::Key::Insert => libbrz::Key::Insert,
         termion::event::Key::F(u) => libbrz::Key::F(u),
         termion::event::Key::Char(c) => libbrz::Key::Char(c),
         termion::event::Key::Alt(c) => libbrz::Key::Alt(c),
         termion::event::Key::Ctrl(c) => libbrz::Key::Ctrl(c),
         termion::event::Key::Null => libbrz::Key::Null,
         termion::event::Key::Esc => libbrz::Key::Esc,
-        _ => unimplemented!(),
+        // `BackTab` and whatever termion adds later
+        _ => return None,
+    })
+}
+
+/// How long to wait for a formatter, before giving up on it
+const FORMATTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Read all of `pipe` in another thread
+fn read_in_thread<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Pipe `text` through the command `cmd`, and return what it printed
+///
+/// Fails if the command exits with an error, with the first line
+/// it printed on stderr as the message, or if it doesn't finish
+/// within `FORMATTER_TIMEOUT` (it gets killed then).
+fn run_formatter(cmd: &[String], text: &Rope) -> io::Result<Rope> {
+    let mut child = process::Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+
+    // write and read from other threads, so big outputs don't block the formatter
+    let mut stdin = child.stdin.take().expect("stdin piped");
+    let text = text.clone();
+    thread::spawn(move || text.write_to(&mut stdin));
+    let stdout = read_in_thread(child.stdout.take());
+    let stderr = read_in_thread(child.stderr.take());
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() > FORMATTER_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("timed out after {}s", FORMATTER_TIMEOUT.as_secs()),
+            ));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr);
+        let msg = stderr
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .map(str::to_owned)
+            .unwrap_or_else(|| format!("{}", status));
+        return Err(io::Error::other(msg));
+    }
+    let stdout =
+        String::from_utf8(stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Rope::from_str(&stdout))
+}
+
+/// `State` with all the handlers hooked up to the local system
+/// and the plugins loaded
+fn init_state(opt: &opts::Opts) -> Result<State> {
+    let mut state: State = default();
+
+    state.register_read_handler(|path| {
+        Rope::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))
+    });
+
+    state.register_write_handler(|path, rope| {
+        let tmp_path = path.with_extension("brz.tmp");
+        rope.write_to(std::io::BufWriter::new(std::fs::File::create(&tmp_path)?))?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    });
+
+    state.register_format_handler(run_formatter);
+
+    // with a daemon, the clients' terminals
+    let on_terminal = !opt.headless && !opt.rpc;
+    let (copy, paste) = clipboard::handlers(on_terminal);
+    state.register_clipboard_handler(copy, paste);
+
+    // walk lazily, when the finder is opened, so it sees the files
+    // created since the last time
+    let file_list: RefCell<Option<FileList>> = RefCell::new(None);
+    state.register_find_handler(move |start| {
+        let mut file_list = file_list.borrow_mut();
+        // a finder that was just opened, unless a walk is still under way
+        // for one opened a moment ago
+        if start == 0 && file_list.as_ref().map(FileList::is_done).unwrap_or(true) {
+            *file_list = Some(FileList::walk_current_dir());
+        }
+        Ok(file_list.as_ref().expect("walking").since(start))
+    });
+
+    // plugins given explicitly are trusted with everything
+    for path in &opt.plugins {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        state.load_plugin(&name, &std::fs::read(path)?, Capability::all(), default())?;
     }
+
+    Ok(state)
 }
 
 /// The editor instance
@@ -40,47 +175,7 @@ struct Breeze {
 }
 
 impl Breeze {
-    fn init() -> Result<Self> {
-        let mut state: State = default();
-
-        state.register_read_handler(|path| {
-            Rope::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))
-        });
-
-        state.register_write_handler(|path, rope| {
-            let tmp_path = path.with_extension("brz.tmp");
-            rope.write_to(std::io::BufWriter::new(std::fs::File::create(&tmp_path)?))?;
-            std::fs::rename(tmp_path, path)?;
-            Ok(())
-        });
-
-        state.register_find_handler(|pattern| {
-            Ok(ignore::Walk::new(".")
-                .into_iter()
-                .filter_map(|entry| entry.ok())
-                .filter(|entry| entry.file_type().map(|f| f.is_file()).unwrap_or(false))
-                .filter(|entry| {
-                    let entry_str = entry
-                        .path()
-                        .to_owned()
-                        .into_os_string()
-                        .to_string_lossy()
-                        .to_string();
-                    let mut entry_str = &entry_str[..];
-
-                    for ch in pattern.chars() {
-                        if let Some(i) = entry_str.find(ch) {
-                            entry_str = &entry_str[i + 1..];
-                        } else {
-                            return false;
-                        }
-                    }
-                    true
-                })
-                .map(|entry| entry.into_path())
-                .take(10)
-                .collect())
-        });
+    fn init(state: State) -> Result<Self> {
         let breeze = Breeze {
             state,
             render: render::Render::new()?,
@@ -103,7 +198,12 @@ impl Breeze {
             // TODO: https://gitlab.redox-os.org/redox-os/termion/issues/151
             match e {
                 Ok(Event::Key(key)) => {
-                    self.state.handle_key(termion_to_brz_key(key));
+                    if let Some(key) = termion_to_brz_key(key) {
+                        self.state.handle_key(key);
+                    }
+                    if let Some(text) = self.state.take_copied() {
+                        self.render.set_clipboard(&text)?;
+                    }
                 }
                 Ok(Event::Unsupported(_u)) => {}
                 Ok(Event::Mouse(_)) => {
@@ -128,10 +228,48 @@ impl Breeze {
 
 fn run() -> Result<()> {
     let opt = opts::Opts::from_args();
-    let mut brz = Breeze::init()?;
 
-    for path in opt.inputs {
-        brz.open(&path)?;
+    if let Some(session) = opt.connect {
+        return client::run(&session);
+    }
+
+    if opt.rpc {
+        let mut state = init_state(&opt)?;
+        for path in &opt.inputs {
+            state.open_buffer(path);
+        }
+        return rpc::run(state);
+    }
+
+    if opt.headless {
+        let mut state = init_state(&opt)?;
+        for path in &opt.inputs {
+            state.open_buffer(path);
+        }
+        let keys_path = opt
+            .keys
+            .as_ref()
+            .expect("`--keys` is required by `--headless`");
+        let keys = parse_keys(&std::fs::read_to_string(keys_path)?)
+            .map_err(|e| failure::format_err!("{}: {}", keys_path.display(), e))?;
+        let mut brz = Headless::new(state, Coord { x: 80, y: 24 });
+        brz.feed(&keys);
+        println!("{}", brz.snapshot());
+        return Ok(());
+    }
+
+    if let Some(ref session) = opt.daemon {
+        let mut state = init_state(&opt)?;
+        for path in &opt.inputs {
+            state.open_buffer(path);
+        }
+        return daemon::run(session, state);
+    }
+
+    let mut brz = Breeze::init(init_state(&opt)?)?;
+
+    for path in &opt.inputs {
+        brz.open(path)?;
     }
 
     brz.run()?;
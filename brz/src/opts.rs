@@ -5,6 +5,42 @@ use std::path::PathBuf;
 #[derive(Debug, StructOpt, Clone)]
 #[structopt(name = "indexer", about = "Bitcoin Indexer")]
 pub struct Opts {
+    /// Start a daemon holding the editor session `name`, for clients to attach to
+    #[structopt(long = "daemon", value_name = "name")]
+    pub daemon: Option<String>,
+
+    /// Attach to the editor session `name` held by a daemon
+    #[structopt(long = "connect", value_name = "name", conflicts_with = "daemon")]
+    pub connect: Option<String>,
+
+    /// Run without a terminal, driven by JSON-RPC requests on stdin
+    #[structopt(long = "rpc", conflicts_with = "daemon", conflicts_with = "connect")]
+    pub rpc: bool,
+
+    /// Run without a terminal, replaying the key script from `--keys`,
+    /// and print the final screen
+    #[structopt(
+        long = "headless",
+        requires = "keys",
+        conflicts_with = "daemon",
+        conflicts_with = "connect",
+        conflicts_with = "rpc"
+    )]
+    pub headless: bool,
+
+    /// Key script to replay in `--headless` mode (eg. `xd3jp<esc>`)
+    #[structopt(long = "keys", value_name = "file", parse(from_os_str))]
+    pub keys: Option<PathBuf>,
+
+    /// Load a WebAssembly plugin (can be given multiple times)
+    #[structopt(
+        long = "plugin",
+        number_of_values = 1,
+        value_name = "path",
+        parse(from_os_str)
+    )]
+    pub plugins: Vec<PathBuf>,
+
     //Input files or directories.
     #[structopt(parse(from_os_str))]
     pub inputs: Vec<PathBuf>,
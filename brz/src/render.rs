@@ -146,32 +146,7 @@ pub struct Render {
 impl Render {
     pub fn new() -> Result<Self> {
         let screen = AlternateScreen::from(std::io::stdout().into_raw_mode().unwrap());
-        let color_map = render::ColorMap {
-            default: render::Style::default(),
-            actions: render::Style {
-                fg: Some(8),
-                bg: Some(14),
-                ..Default::default()
-            },
-            line_num: render::Style {
-                fg: Some(10),
-                ..Default::default()
-            },
-            direction_marker: render::Style {
-                fg: Some(16),
-                bg: Some(4),
-                ..Default::default()
-            },
-            selection: render::Style {
-                fg: Some(16),
-                bg: Some(4),
-                ..Default::default()
-            },
-            special: render::Style {
-                fg: Some(14),
-                ..Default::default()
-            },
-        };
+        let color_map = render::default_color_map();
 
         let mut s = Render {
             display_cols: 0,
@@ -212,6 +187,34 @@ impl Render {
 
     pub fn draw(&mut self, state: &State) -> Result<()> {
         state.render(self);
+        self.flush()
+    }
+
+    /// Draw a `Frame` rendered elsewhere (eg. by a daemon)
+    pub fn draw_frame(&mut self, frame: &render::Frame) -> Result<()> {
+        use libbrz::render::Renderer;
+
+        for y in 0..frame.dimensions.y {
+            for x in 0..frame.dimensions.x {
+                let coord = render::Coord { x, y };
+                if let Some((ch, style)) = frame.get(coord) {
+                    self.put(coord, ch, style);
+                }
+            }
+        }
+        self.set_cursor(frame.cursor);
+        self.flush()
+    }
+
+    /// Put `text` into the clipboard of the terminal
+    pub fn set_clipboard(&mut self, text: &str) -> Result<()> {
+        self.screen
+            .write_all(crate::clipboard::osc52(text).as_bytes())?;
+        self.screen.flush()?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
         let mut buf = CachingAnsciWriter::default();
         if self.prev_buffer.is_empty() {
             self.prev_buffer
@@ -0,0 +1,35 @@
+//! Scripting session over stdin/stdout, one JSON-RPC message per line
+
+use libbrz::rpc::Rpc;
+use libbrz::{prelude::*, state::State};
+
+use std::io::{self, BufRead, Write};
+
+pub fn run(mut state: State) -> Result<()> {
+    let mut rpc = Rpc::new(&state);
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(response) = rpc.handle(&mut state, &line) {
+            writeln!(out, "{}", response)?;
+        }
+        for event in rpc.poll_events(&state) {
+            writeln!(out, "{}", event)?;
+        }
+        out.flush()?;
+
+        if state.is_finished() {
+            break;
+        }
+    }
+
+    Ok(())
+}
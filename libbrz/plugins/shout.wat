@@ -0,0 +1,100 @@
+;; Example plugin
+;;
+;; * `shout` action (`a-S`) - uppercase ASCII letters in the current buffer
+;; * `count` command - display the number of chars in the current buffer
+;; * `spin`, `grow` and `huge` commands - misbehave, to test the sandbox limits
+(module
+  (import "brz" "message" (func $message (param i32 i32)))
+  (import "brz" "buffer_len" (func $buffer_len (result i32)))
+  (import "brz" "buffer_read" (func $buffer_read (param i32 i32 i32 i32) (result i32)))
+  (import "brz" "buffer_replace" (func $buffer_replace (param i32 i32 i32 i32) (result i32)))
+  (import "brz" "buffer_insert" (func $buffer_insert (param i32 i32) (result i32)))
+  (import "brz" "register_action" (func $register_action (param i32 i32 i32 i32) (result i32)))
+  (import "brz" "map_key" (func $map_key (param i32 i32 i32 i32) (result i32)))
+  (import "brz" "register_command" (func $register_command (param i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+
+  (data (i32.const 0) "shout")
+  (data (i32.const 8) "uppercase the buffer")
+  (data (i32.const 32) "a-S")
+  (data (i32.const 40) "count")
+  (data (i32.const 48) "spin")
+  (data (i32.const 56) "grow")
+  (data (i32.const 64) "grow failed")
+  (data (i32.const 80) "grew")
+  (data (i32.const 96) "too big")
+  (data (i32.const 104) "huge")
+
+  ;; text is read into 1024..(1024 + 60000)
+  (global $text i32 (i32.const 1024))
+  (global $text_cap i32 (i32.const 60000))
+
+  (func (export "init")
+    (drop (call $register_action (i32.const 0) (i32.const 5) (i32.const 8) (i32.const 20)))
+    (drop (call $map_key (i32.const 32) (i32.const 3) (i32.const 0) (i32.const 5)))
+    (drop (call $register_command (i32.const 40) (i32.const 5)))
+    (drop (call $register_command (i32.const 48) (i32.const 4)))
+    (drop (call $register_command (i32.const 56) (i32.const 4)))
+    (drop (call $register_command (i32.const 104) (i32.const 4)))
+    ;; way past the end of the memory, so it fails
+    (drop (call $register_command (i32.const 0) (i32.const 0x7fffffff))))
+
+  (func $shout
+    (local $len i32)
+    (local $n i32)
+    (local $p i32)
+    (local $ch i32)
+    (local.set $len (call $buffer_len))
+    (if (i32.lt_s (local.get $len) (i32.const 0)) (then (return)))
+    (local.set $n
+      (call $buffer_read (i32.const 0) (local.get $len) (global.get $text) (global.get $text_cap)))
+    (if (i32.gt_s (local.get $n) (global.get $text_cap))
+      (then
+        (call $message (i32.const 96) (i32.const 7))
+        (return)))
+    (local.set $p (global.get $text))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $p) (i32.add (global.get $text) (local.get $n))))
+        (local.set $ch (i32.load8_u (local.get $p)))
+        (if (i32.and
+              (i32.ge_u (local.get $ch) (i32.const 97))
+              (i32.le_u (local.get $ch) (i32.const 122)))
+          (then (i32.store8 (local.get $p) (i32.sub (local.get $ch) (i32.const 32)))))
+        (local.set $p (i32.add (local.get $p) (i32.const 1)))
+        (br $next)))
+    (drop (call $buffer_replace (i32.const 0) (local.get $len) (global.get $text) (local.get $n))))
+
+  ;; display the number of chars, formatted backwards from 220
+  (func $count
+    (local $n i32)
+    (local $p i32)
+    (local.set $n (call $buffer_len))
+    (local.set $p (i32.const 220))
+    (loop $digit
+      (local.set $p (i32.sub (local.get $p) (i32.const 1)))
+      (i32.store8 (local.get $p)
+        (i32.add (i32.const 48) (i32.rem_u (local.get $n) (i32.const 10))))
+      (local.set $n (i32.div_u (local.get $n) (i32.const 10)))
+      (br_if $digit (i32.ne (local.get $n) (i32.const 0))))
+    (call $message (local.get $p) (i32.sub (i32.const 220) (local.get $p))))
+
+  (func (export "on_action") (param $id i32)
+    (if (i32.eq (local.get $id) (i32.const 0)) (then (call $shout))))
+
+  (func (export "on_command") (param $id i32)
+    (block $huge
+      (block $grow
+        (block $spin
+          (block $count
+            (br_table $count $spin $grow $huge (local.get $id)))
+          (call $count)
+          (return))
+        (loop $forever (br $forever)))
+      (if (i32.lt_s (memory.grow (i32.const 1000)) (i32.const 0))
+        (then (call $message (i32.const 64) (i32.const 11)))
+        (else (call $message (i32.const 80) (i32.const 4))))
+      (return))
+    (if (i32.lt_s (call $buffer_insert (i32.const 0) (i32.const 0x7fffffff)) (i32.const 0))
+      (then (call $message (i32.const 96) (i32.const 7))))))
@@ -50,7 +50,23 @@ pub fn actions() -> &'static super::ActionsById {
             },
 
             OpenFile, "open mode", (state) {
-                state.set_mode(mode::Find::default());
+                state.set_mode(mode::Picker::find_file());
+            },
+
+            OpenRecentFile, "open recent file", (state) {
+                state.set_mode(mode::Picker::recent_file());
+            },
+
+            PickBuffer, "switch buffer", (state) {
+                state.set_mode(mode::Picker::buffer());
+            },
+
+            PickAction, "action palette", (state) {
+                state.set_mode(mode::Picker::action());
+            },
+
+            PickSymbol, "jump to symbol", (state) {
+                state.set_mode(mode::Picker::symbol());
             },
 
             MoveUpPage, "move up page", (state) {
@@ -72,11 +88,42 @@ pub fn actions() -> &'static super::ActionsById {
             },
 
 
+            ToggleComment, "toggle comment", (state) {
+                if !state.cur_buffer_mut().toggle_comment() {
+                    let name = state.cur_buffer().filetype.name;
+                    state.msg = Some(format!("No comments in {} files", name));
+                }
+            },
+
+            YankToClipboard, "yank to system clipboard", (state) {
+                state.yank_to_clipboard();
+            },
+
+            PasteFromClipboard, "paste from system clipboard", (state) {
+                if let Some(yanked) = state.clipboard_yanked() {
+                    state.cur_buffer_mut().paste(&yanked);
+                }
+            },
+
+            PasteFromClipboardExtend, "paste from system clipboard (extend)", (state) {
+                if let Some(yanked) = state.clipboard_yanked() {
+                    state.cur_buffer_mut().paste_extend(&yanked);
+                }
+            },
+
             OpenLine, "open line", (state) {
                 state.cur_buffer_mut().open();
                 state.set_mode(mode::Insert::new_normal());
             },
 
+            SelectObjectInner, "select inner object", (state) {
+                state.set_mode(mode::Object::inner());
+            },
+
+            SelectObjectAround, "select object", (state) {
+                state.set_mode(mode::Object::around());
+            },
+
             SelectInnerSurrounding, "select inner surrounding", (state) {
                 state.cur_buffer_mut().select_inner_surrounding();
             },
@@ -101,14 +148,23 @@ pub fn default_key_mappings() -> &'static super::KeyMappings {
             { a, LineAppend },
             { A, LineAppendExtend },
             { c p, OpenFile },
+            { c r, OpenRecentFile },
+            { c b, PickBuffer },
+            { a x, PickAction },
+            { c t, PickSymbol },
             { c u, MoveUpPage },
             { c d, MoveDownPage },
             { c U, ExtendUpPage },
             { c D, ExtendDownPage },
             { '>', IndentRight },
             { '<', IndentLeft },
+            { c c, ToggleComment },
+            { a y, YankToClipboard },
+            { a p, PasteFromClipboard },
+            { a P, PasteFromClipboardExtend },
             { 'o', OpenLine },
-            { a i, SelectInnerSurrounding },
+            { a i, SelectObjectInner },
+            { a a, SelectObjectAround },
             { a I, ExpandInnerSurrounding },
         );
         m
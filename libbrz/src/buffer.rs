@@ -1,10 +1,20 @@
 #![allow(dead_code)]
-use crate::{idx::*, position::*, prelude::*, selection::*, util::char};
+use crate::filetype::{self, FileType};
+use crate::{editorconfig, indent};
+use crate::{
+    idx::*,
+    position::*,
+    prelude::*,
+    selection::*,
+    text_object::TextObject,
+    util::{case, char, diff, number},
+};
 use ropey::Rope;
 use std::cell::RefCell;
-use std::cmp::{max, min};
-use std::collections::BTreeSet;
+use std::cmp::{max, min, Ordering};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
+use std::sync::atomic::{self, AtomicU64};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum VisualSelection {
@@ -13,6 +23,16 @@ pub enum VisualSelection {
     None,
 }
 
+/// Char search of `f`, `t` and friends
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FindChar {
+    pub ch: char,
+    pub forward: bool,
+    /// Stop right before the char, instead of right after it
+    pub till: bool,
+    pub extend: bool,
+}
+
 pub fn distance_to_next_tabstop(visual_column: usize, tabstop: usize) -> usize {
     let next_tabstop = (visual_column + tabstop) / tabstop * tabstop;
     next_tabstop - visual_column
@@ -123,6 +143,15 @@ impl SelectionSet {
         }
     }
 
+    /// Make sure no selection points past the end of the `text`
+    pub fn trim_to_text(&mut self, text: &Rope) {
+        for sel in &mut self.selections {
+            sel.anchor = sel.anchor.trim_to_text(text);
+            sel.cursor = sel.cursor.trim_to_text(text);
+        }
+        self.normalize();
+    }
+
     pub fn clear_cursor_column(&mut self) {
         self.cursor_column.clear();
     }
@@ -140,6 +169,93 @@ impl SelectionSet {
             *sel = sel.sorted()
         }
     }
+
+    /// Order the selections by position, and merge the ones that overlap or touch
+    ///
+    /// The primary selection is tracked through the merge. Merged selection
+    /// keeps the direction of the first of the merged ones.
+    pub fn normalize(&mut self) {
+        if self.selections.len() < 2 {
+            self.primary = 0;
+            return;
+        }
+
+        let cursor_column = if self.cursor_column.len() == self.selections.len() {
+            std::mem::take(&mut self.cursor_column)
+        } else {
+            vec![]
+        };
+
+        let mut sorted: Vec<_> = self
+            .selections
+            .iter()
+            .enumerate()
+            .map(|(i, sel)| (*sel, i, cursor_column.get(i).cloned()))
+            .collect();
+        sorted.sort_by_key(|(sel, _, _)| sel.sorted_pair());
+
+        let mut selections: Vec<Selection> = Vec::with_capacity(sorted.len());
+        let mut primary = 0;
+        self.cursor_column.clear();
+        for (sel, i, column) in sorted {
+            let (start, end) = sel.sorted_pair();
+            if let Some(last) = selections.last_mut() {
+                let (last_start, last_end) = last.sorted_pair();
+                if start <= last_end {
+                    let merged = Selection::new_from_normalized(last_start, max(end, last_end));
+                    *last = last.unify_direction_of(merged);
+                    if i == self.primary {
+                        primary = selections.len() - 1;
+                    }
+                    continue;
+                }
+            }
+            if i == self.primary {
+                primary = selections.len();
+            }
+            selections.push(sel);
+            if let Some(column) = column {
+                self.cursor_column.push(column);
+            }
+        }
+
+        self.selections = selections;
+        self.primary = primary;
+    }
+
+    /// Do the selections keep the invariant that `normalize` establishes
+    pub fn is_normalized(&self) -> bool {
+        self.primary < self.selections.len()
+            && self
+                .selections
+                .windows(2)
+                .all(|w| w[0].sorted_pair().1 < w[1].sorted_pair().0)
+    }
+}
+
+/// How `Buffer::sort_contents` compares the contents
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SortBy {
+    Lexical,
+    /// By the number at the start, eg. `9` before `10`
+    Numeric,
+    CaseInsensitive,
+}
+
+/// Source of the `Buffer::version`s
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn next_version() -> u64 {
+    NEXT_VERSION.fetch_add(1, atomic::Ordering::Relaxed)
+}
+
+fn leading_number(s: &str) -> Option<f64> {
+    let s = s.trim_start();
+    let end = s
+        .char_indices()
+        .find(|&(i, ch)| !(ch.is_ascii_digit() || ch == '.' || (i == 0 && ch == '-')))
+        .map_or(s.len(), |(i, _)| i);
+    s[..end].parse().ok()
 }
 
 /// Buffer
@@ -151,11 +267,20 @@ pub struct Buffer {
     pub selection: SelectionSet,
 
     pub path: Option<PathBuf>,
+    pub filetype: &'static FileType,
 
     pub tabstop: usize,
     pub expand_tabs: bool,
 
     pub view_line_offset: RefCell<usize>,
+
+    /// Selections of the other windows showing this buffer, by window
+    ///
+    /// Kept here, so the edits move them too.
+    pub(crate) window_selections: BTreeMap<usize, SelectionSet>,
+
+    /// See `version`
+    version: u64,
 }
 
 impl Default for Buffer {
@@ -166,7 +291,10 @@ impl Default for Buffer {
             selection: default(),
             expand_tabs: true,
             path: None,
+            filetype: &filetype::PLAIN,
             view_line_offset: RefCell::new(0),
+            window_selections: BTreeMap::new(),
+            version: next_version(),
         }
     }
 }
@@ -176,6 +304,118 @@ impl Buffer {
         Self { text, ..default() }
     }
 
+    /// Id of the text, new after every edit
+    ///
+    /// No two texts get the same one, even in different buffers, so it is
+    /// a cheap way to tell if the text changed, eg. after an undo.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Set the indentation from the text, unless the file type says otherwise
+    pub fn detect_indent(&mut self) {
+        if let Some(detected) = indent::detect(&self.text) {
+            self.expand_tabs = detected.expand_tabs;
+            if let Some(width) = detected.width {
+                self.tabstop = width;
+            }
+        }
+        if let Some(expand_tabs) = self.filetype.expand_tabs {
+            self.expand_tabs = expand_tabs;
+        }
+        if let Some(width) = self.filetype.indent_width {
+            self.tabstop = width;
+        }
+    }
+
+    /// Use the indentation set in `.editorconfig`, over the detected one
+    pub fn apply_editorconfig(&mut self, props: &editorconfig::Properties) {
+        if let Some(style) = props.indent_style {
+            self.expand_tabs = style == editorconfig::IndentStyle::Space;
+        }
+        let width = if self.expand_tabs {
+            props.indent_width().or(props.tab_width)
+        } else {
+            props.tab_width.or_else(|| props.indent_width())
+        };
+        if let Some(width) = width {
+            self.tabstop = width;
+        }
+    }
+
+    /// Remove the whitespace at the ends of lines
+    pub fn trim_trailing_whitespace(&mut self) {
+        for line in (0..self.text.len_lines()).rev() {
+            let start = self.text.line_to_char(line);
+            let line_str = self.text.line(line).to_string();
+            let content = line_str.trim_end_matches(&['\n', '\r'][..]);
+            let trimmed = content.trim_end();
+            let len = content.chars().count() - trimmed.chars().count();
+            if len > 0 {
+                self.replace_at(Idx(start + trimmed.chars().count()), len, "");
+            }
+        }
+    }
+
+    /// Change the text to `new`, editing only the lines that differ, so
+    /// the selections stay on the text they were on
+    pub fn replace_text(&mut self, new: &Rope) {
+        let old_lines: Vec<String> = self.text.lines().map(|l| l.to_string()).collect();
+        let new_lines: Vec<String> = new.lines().map(|l| l.to_string()).collect();
+
+        self.selection.clear_cursor_column();
+        for (old_range, new_range) in diff::diff(&old_lines, &new_lines).into_iter().rev() {
+            let old: Vec<char> = old_lines[old_range.clone()].concat().chars().collect();
+            let new: Vec<char> = new_lines[new_range].concat().chars().collect();
+            // narrow down to the changed chars, eg. just the indentation
+            let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+            let suffix = old[prefix..]
+                .iter()
+                .rev()
+                .zip(new[prefix..].iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let start = self.text.line_to_char(old_range.start) + prefix;
+            let old_len = old.len() - prefix - suffix;
+            let replacement: String = new[prefix..new.len() - suffix].iter().collect();
+            self.replace_at(Idx(start), old_len, &replacement);
+        }
+    }
+
+    /// Make the text end with a newline, or make sure it doesn't
+    pub fn set_final_newline(&mut self, final_newline: bool) {
+        let len = self.text.len_chars();
+        let last = if len > 0 {
+            Some(self.text.char(len - 1))
+        } else {
+            None
+        };
+        match (final_newline, last) {
+            (true, Some(ch)) if ch != '\n' => self.replace_at(Idx(len), 0, "\n"),
+            (false, Some('\n')) => {
+                let mut start = len;
+                while start > 0 {
+                    let ch = self.text.char(start - 1);
+                    if ch != '\n' && ch != '\r' {
+                        break;
+                    }
+                    start -= 1;
+                }
+                self.replace_at(Idx(start), len - start, "");
+            }
+            _ => {}
+        }
+    }
+
+    /// Short description of the indentation, eg. `spaces:4`
+    pub fn indent_status(&self) -> String {
+        if self.expand_tabs {
+            format!("spaces:{}", self.tabstop)
+        } else {
+            format!("tabs:{}", self.tabstop)
+        }
+    }
+
     pub fn lines(&self) -> usize {
         self.text.len_lines()
     }
@@ -224,14 +464,16 @@ impl Buffer {
             ..
         } = *self;
 
-        selection
+        let res = selection
             .selections
             .iter_mut()
             .map(|sel| {
                 let res = f(sel, text);
                 res
             })
-            .collect()
+            .collect();
+        selection.normalize();
+        res
     }
 
     fn map_each_enumerated_selection<F, R>(&self, mut f: F) -> Vec<R>
@@ -262,31 +504,28 @@ impl Buffer {
             ..
         } = *self;
 
-        selection
+        let res = selection
             .selections
             .iter_mut()
             .enumerate()
             .map(|(i, sel)| f(i, sel, text))
-            .collect()
+            .collect();
+        selection.normalize();
+        res
     }
 
     pub fn idx_selection_type(&self, idx: Idx) -> VisualSelection {
-        if self.selection.selections.iter().any(|sel| {
-            /*sel.is_empty()
-            && */
-            sel.normalized(&self.text)
-                .is_idx_inside_direction_marker(idx, &self.text)
-        }) {
-            VisualSelection::DirectionMarker
-        } else if self
-            .selection
-            .selections
-            .iter()
-            .any(|sel| sel.normalized(&self.text).is_idx_strictly_inside(idx))
-        {
-            VisualSelection::Selection
-        } else {
-            VisualSelection::None
+        // selections are sorted and don't overlap, so the only one that
+        // can contain `idx` is the first one ending after it
+        let selections = &self.selection.selections;
+        let i = selections.partition_point(|sel| sel.normalized(&self.text).sorted_pair().1 <= idx);
+
+        match selections.get(i).map(|sel| sel.normalized(&self.text)) {
+            Some(sel) if sel.is_idx_inside_direction_marker(idx, &self.text) => {
+                VisualSelection::DirectionMarker
+            }
+            Some(sel) if sel.is_idx_strictly_inside(idx) => VisualSelection::Selection,
+            _ => VisualSelection::None,
         }
     }
 
@@ -317,11 +556,12 @@ impl Buffer {
                 }
                 self.selection.sort();
                 self.selection.fix_on_insert(idx, n);
-                self.text.insert(idx.0, &" ".repeat(n));
+                self.insert_text(idx, &" ".repeat(n));
             }
         } else {
             self.insert_char('\t', extend);
         }
+        self.selection.normalize();
     }
 
     pub fn insert(&mut self, s: &str, extend: bool) {
@@ -338,10 +578,11 @@ impl Buffer {
 
         for idx in insertion_points {
             if !s.is_empty() {
-                self.selection.fix_on_insert(idx, s.len());
-                self.text.insert(idx.0, s);
+                self.selection.fix_on_insert(idx, s.chars().count());
+                self.insert_text(idx, s);
             }
         }
+        self.selection.normalize();
     }
 
     pub fn insert_enter(&mut self, extend: bool) {
@@ -372,14 +613,14 @@ impl Buffer {
 
         for (i, (_, indent, insert_idx, increase_indent)) in indents.iter().enumerate() {
             let mut inserted_len = 0;
-            self.text.insert(insert_idx.0, &indent.to_string());
+            self.insert_text(*insert_idx, &indent.to_string());
             inserted_len += indent.len_chars();
             if *increase_indent {
-                let indent_text = &self.indent_text(1);
-                self.text.insert(insert_idx.0, &indent_text);
+                let indent_text = self.indent_text(1);
+                self.insert_text(*insert_idx, &indent_text);
                 inserted_len += indent_text.len();
             }
-            self.text.insert_char(insert_idx.0, '\n');
+            self.insert_text(*insert_idx, "\n");
             inserted_len += 1;
 
             self.selection.fix_on_insert(*insert_idx, inserted_len);
@@ -387,6 +628,7 @@ impl Buffer {
             sel.cursor = insert_idx.forward_n(inserted_len, &self.text);
             *sel = if extend { *sel } else { sel.collapsed() };
         }
+        self.selection.normalize();
     }
 
     pub fn delete(&mut self) -> Vec<Rope> {
@@ -420,48 +662,306 @@ impl Buffer {
         })
     }
 
+    /// Replace the content of every selection with `f(i, content)`
+    ///
+    /// As in `yank`, an empty selection covers the char before the cursor.
+    /// The selections are kept over the replaced content.
+    pub fn replace_selections_with<F>(&mut self, mut f: F)
+    where
+        F: FnMut(usize, &str) -> String,
+    {
+        let replacements = self.map_each_enumerated_selection(|i, sel, text| {
+            let range = sel.normalized(text).sorted_range_usize();
+            let replacement = f(i, &text.slice(range.clone()).to_string());
+            (i, range, replacement)
+        });
+        self.replace_selection_ranges(replacements, true);
+    }
+
+    /// Replace `(selection index, range, replacement)`s, and move the
+    /// selections along
+    ///
+    /// Each selection ends up over its replacement (or right after it,
+    /// if it was empty and `keep_empty`). Ranges overlapping the previous
+    /// ones are skipped (eg. two empty selections can cover the same char).
+    fn replace_selection_ranges(
+        &mut self,
+        mut replacements: Vec<(usize, std::ops::Range<usize>, String)>,
+        keep_empty: bool,
+    ) {
+        self.selection.clear_cursor_column();
+        replacements.sort_by_key(|(_, range, _)| range.start);
+        let mut last_end = 0;
+        replacements.retain(|(_, range, _)| {
+            let keep = last_end <= range.start;
+            if keep {
+                last_end = range.end;
+            }
+            keep
+        });
+
+        let spans: Vec<_> = replacements
+            .iter()
+            .map(|(_, range, replacement)| (range.clone(), replacement.chars().count()))
+            .collect();
+        // where a char index ends up after the replacements
+        let map_idx = |idx: Idx| {
+            let mut delta = 0isize;
+            for (range, new_len) in &spans {
+                if range.end <= idx.0 {
+                    delta += *new_len as isize - range.len() as isize;
+                } else if range.start < idx.0 {
+                    let start = (range.start as isize + delta) as usize;
+                    return Idx(start + min(idx.0 - range.start, *new_len));
+                } else {
+                    break;
+                }
+            }
+            Idx((idx.0 as isize + delta) as usize)
+        };
+
+        let mut replaced_sels = BTreeSet::new();
+        for (i, range, replacement) in &replacements {
+            replaced_sels.insert(*i);
+            let start = map_idx(Idx(range.start));
+            let end = Idx(start.0 + replacement.chars().count());
+            let sel = &mut self.selection.selections[*i];
+            *sel = if keep_empty && sel.is_empty() {
+                Selection::new_from_normalized(end, end)
+            } else {
+                sel.unify_direction_of(Selection::new_from_normalized(start, end))
+            };
+        }
+        for (i, sel) in self.selection.selections.iter_mut().enumerate() {
+            if !replaced_sels.contains(&i) {
+                sel.anchor = map_idx(sel.anchor);
+                sel.cursor = map_idx(sel.cursor);
+            }
+        }
+
+        for (_, range, replacement) in replacements.into_iter().rev() {
+            self.remove_text(range.clone());
+            self.insert_text(Idx(range.start), &replacement);
+        }
+        self.selection.normalize();
+    }
+
+    /// Add `delta` to the number at or after every selection, and select it
+    pub fn increment_numbers(&mut self, delta: i64) {
+        let replacements = self
+            .map_each_enumerated_selection(|i, sel, text| {
+                let (start, end) = sel.sorted_pair().0.number_literal_at_or_after(text)?;
+                let literal = text.slice(start.0..end.0).to_string();
+                let replacement = number::add_to_literal(&literal, delta)?;
+                Some((i, start.0..end.0, replacement))
+            })
+            .into_iter()
+            .flatten()
+            .collect();
+        self.replace_selection_ranges(replacements, false);
+    }
+
+    /// Replace the selections with a sequence of numbers: `start`,
+    /// `start + step`, ..., zero-padded to `width` digits
+    ///
+    /// Returns `false`, without changing anything, if the sequence
+    /// doesn't fit in an `i64`.
+    pub fn insert_number_sequence(&mut self, start: i64, step: i64, width: usize) -> bool {
+        let numbers: Option<Vec<i64>> = (0..self.selection.selections.len() as i64)
+            .map(|i| step.checked_mul(i).and_then(|n| start.checked_add(n)))
+            .collect();
+        let numbers = match numbers {
+            Some(numbers) => numbers,
+            None => return false,
+        };
+        self.replace_selections_with(|i, _| {
+            let n = numbers[i];
+            if n < 0 {
+                // through `i128`, as `-i64::MIN` doesn't fit
+                format!("-{:0width$}", -i128::from(n), width = width)
+            } else {
+                format!("{:0width$}", n, width = width)
+            }
+        });
+        true
+    }
+
+    /// Contents of the selections, as `yank` sees them
+    pub fn selection_contents(&self) -> Vec<String> {
+        self.map_each_selection(|sel, text| {
+            text.slice(sel.normalized(text).sorted_range_usize())
+                .to_string()
+        })
+    }
+
+    /// Replace the contents of the selections with `f` of all of them
+    ///
+    /// `f` has to return as many contents as it was given.
+    fn permute_contents<F>(&mut self, f: F)
+    where
+        F: FnOnce(Vec<String>) -> Vec<String>,
+    {
+        let contents = f(self.selection_contents());
+        self.replace_selections_with(|i, s| {
+            contents.get(i).cloned().unwrap_or_else(|| s.to_owned())
+        });
+    }
+
+    pub fn sort_contents(&mut self, sort_by: SortBy) {
+        self.permute_contents(|mut contents| {
+            match sort_by {
+                SortBy::Lexical => contents.sort(),
+                SortBy::CaseInsensitive => contents.sort_by_key(|s| s.to_lowercase()),
+                // non-numbers go last, in their original order
+                SortBy::Numeric => contents.sort_by(|a, b| {
+                    let (a, b) = (leading_number(a), leading_number(b));
+                    match (a, b) {
+                        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                        (Some(_), None) => Ordering::Less,
+                        (None, Some(_)) => Ordering::Greater,
+                        (None, None) => Ordering::Equal,
+                    }
+                }),
+            }
+            contents
+        });
+    }
+
+    /// Remove the contents of the selections that repeat the ones of
+    /// the selections before them
+    pub fn unique_contents(&mut self) {
+        self.permute_contents(|contents| {
+            let mut seen = std::collections::HashSet::new();
+            contents
+                .into_iter()
+                .map(|s| {
+                    if seen.insert(s.clone()) {
+                        s
+                    } else {
+                        String::new()
+                    }
+                })
+                .collect()
+        });
+    }
+
+    pub fn reverse_contents(&mut self) {
+        self.permute_contents(|mut contents| {
+            contents.reverse();
+            contents
+        });
+    }
+
+    /// Move the contents of each selection `n` selections forward
+    /// (or backward), wrapping around
+    pub fn rotate_contents(&mut self, n: isize) {
+        self.permute_contents(|mut contents| {
+            if !contents.is_empty() {
+                let n = n.rem_euclid(contents.len() as isize) as usize;
+                contents.rotate_right(n);
+            }
+            contents
+        });
+    }
+
+    pub fn to_lowercase(&mut self) {
+        self.replace_selections_with(|_, s| s.to_lowercase());
+    }
+
+    pub fn to_uppercase(&mut self) {
+        self.replace_selections_with(|_, s| s.to_uppercase());
+    }
+
+    pub fn swap_case(&mut self) {
+        self.replace_selections_with(|_, s| case::swap_case(s));
+    }
+
+    /// Cycle the case style of the identifiers in the selections
+    ///
+    /// Empty selections are extended to the word around them first.
+    pub fn cycle_identifier_case(&mut self) {
+        self.map_each_selection_mut(|sel, text| {
+            if sel.is_empty() {
+                if let Some((start, end)) = TextObject::Word.find(*sel, false, text) {
+                    *sel = Selection::new_from_normalized(start, end);
+                }
+            }
+        });
+        self.replace_selections_with(|_, s| case::cycle_identifier_case(s));
+    }
+
+    /// Paste `yanked[i]` at the cursor of the `i`-th selection
     pub fn paste(&mut self, yanked: &[Rope]) {
-        let mut insertion_points = self.map_each_selection_mut(|sel, _text| (sel.cursor));
-        insertion_points.sort();
+        let mut insertion_points =
+            self.map_each_enumerated_selection(|i, sel, _text| (i, sel.cursor));
+        // from the end, so the earlier indices stay valid
+        insertion_points.sort_by_key(|&(_, idx)| idx);
         insertion_points.reverse();
 
-        for (i, idx) in insertion_points.iter().enumerate() {
+        for (i, idx) in &insertion_points {
             self.selection.collapse();
-            if let Some(to_yank) = yanked.get(i) {
+            if let Some(to_yank) = yanked.get(*i) {
                 self.selection.fix_on_insert(*idx, to_yank.len_chars());
-                for chunk in to_yank.chunks() {
-                    self.text.insert(idx.0, chunk);
-                }
+                self.insert_text(*idx, &to_yank.to_string());
             }
         }
+        self.selection.normalize();
     }
 
+    /// Like `paste`, but extending the selections over the pasted text
     pub fn paste_extend(&mut self, yanked: &[Rope]) {
-        let mut insertion_points = self.map_each_enumerated_selection(|_i, sel, _text| sel.cursor);
-        insertion_points.sort();
+        let mut insertion_points =
+            self.map_each_enumerated_selection(|i, sel, _text| (i, sel.cursor));
+        insertion_points.sort_by_key(|&(_, idx)| idx);
         insertion_points.reverse();
 
-        for (i, idx) in insertion_points.iter().enumerate() {
-            if let Some(to_yank) = yanked.get(i) {
+        for (i, idx) in &insertion_points {
+            if let Some(to_yank) = yanked.get(*i) {
                 self.selection.fix_on_insert(*idx, to_yank.len_chars());
-                for chunk in to_yank.chunks() {
-                    self.text.insert(idx.0, chunk);
-                }
+                self.insert_text(*idx, &to_yank.to_string());
             }
         }
+        self.selection.normalize();
     }
 
     /// Remove text at given ranges
     ///
-    /// `removal_points` contains list of `(selection_index, range)`,
+    /// Ranges can overlap (eg. when selections do), in which case
+    /// their union is removed.
     fn remove_ranges(&mut self, mut removal_points: Vec<std::ops::Range<usize>>) {
         removal_points.sort_by(|a, b| a.start.cmp(&b.start));
-        removal_points.reverse();
 
+        let mut merged: Vec<std::ops::Range<usize>> = vec![];
         for range in removal_points {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = max(last.end, range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged.reverse();
+
+        for range in merged {
             self.selection
                 .fix_on_delete(Idx(range.start), range.len(), &self.text);
-            self.text.remove(range.clone());
+            self.remove_text(range);
+        }
+        self.selection.normalize();
+    }
+
+    /// Replace the text in `range` with `s`, adjusting the selections
+    pub fn replace_range(&mut self, range: std::ops::Range<Idx>, s: &str) {
+        self.selection.clear_cursor_column();
+
+        if range.start < range.end {
+            self.selection
+                .fix_on_delete(range.start, range.end.0 - range.start.0, &self.text);
+            self.remove_text(range.start.0..range.end.0);
+            self.selection.normalize();
+        }
+
+        if !s.is_empty() {
+            self.selection.fix_on_insert(range.start, s.chars().count());
+            self.insert_text(range.start, s);
         }
     }
 
@@ -469,7 +969,7 @@ impl Buffer {
         self.selection.clear_cursor_column();
         let removal_points = self.map_each_enumerated_selection_mut(|_, sel, text| {
             let sel_aligned = sel.normalized(text);
-            let range = (sel_aligned.cursor.0 - 1)..sel_aligned.cursor.0;
+            let range = sel_aligned.cursor.0.saturating_sub(1)..sel_aligned.cursor.0;
             *sel = sel.collapsed();
 
             range
@@ -481,7 +981,7 @@ impl Buffer {
     pub fn backspace(&mut self, extend: bool) {
         self.selection.clear_cursor_column();
         if self.expand_tabs {
-            let mut removal = self.map_each_selection(|sel, text| {
+            let removal: Vec<_> = self.map_each_selection(|sel, text| {
                 let v_col = self.to_visual(sel.cursor.to_position(text)).column;
 
                 (
@@ -496,24 +996,78 @@ impl Buffer {
                 )
             });
 
-            removal.sort_by_key(|r| r.0);
-            removal.reverse();
+            let removal = removal
+                .into_iter()
+                .map(|(idx, n)| idx.backward_n(n, &self.text).0..idx.0)
+                .collect();
 
             if !extend {
                 self.selection.collapse();
                 self.selection.sort();
             }
-            for (idx, n) in removal {
-                let start = idx.backward_n(n, &self.text);
-                self.selection
-                    .fix_on_delete(start, idx.0 - start.0, &self.text);
-                self.text.remove(start.0..idx.0);
-            }
+            self.remove_ranges(removal);
         } else {
             self.backspace_one();
         }
     }
 
+    /// Is every cursor right before `ch`
+    pub fn cursors_before(&self, ch: char) -> bool {
+        self.selection
+            .selections
+            .iter()
+            .all(|sel| sel.cursor.next_char(&self.text) == Some(ch))
+    }
+
+    /// Is every cursor right between `open` and `close`
+    pub fn cursors_between(&self, open: char, close: char) -> bool {
+        self.selection.selections.iter().all(|sel| {
+            sel.cursor.prev_char(&self.text) == Some(open)
+                && sel.cursor.next_char(&self.text) == Some(close)
+        })
+    }
+
+    /// Is any cursor right after a word-forming char
+    pub fn any_cursor_after_word(&self) -> bool {
+        self.selection.selections.iter().any(|sel| {
+            let prev = sel.cursor.prev_char(&self.text);
+            matches!(prev, Some(ch) if char::is_word_forming(ch))
+        })
+    }
+
+    /// Insert `open` and `close` at every cursor, with the cursor between them
+    pub fn insert_pair(&mut self, open: char, close: char, extend: bool) {
+        self.insert(&format!("{}{}", open, close), extend);
+        self.map_each_selection_mut(|sel, text| {
+            sel.cursor = sel.cursor.backward(text);
+            if !extend {
+                sel.anchor = sel.cursor;
+            }
+        });
+    }
+
+    /// Move every cursor over the char after it
+    pub fn step_over(&mut self, extend: bool) {
+        self.selection.clear_cursor_column();
+        self.map_each_selection_mut(|sel, text| {
+            sel.cursor = sel.cursor.forward(text);
+            if !extend {
+                sel.anchor = sel.cursor;
+            }
+        });
+    }
+
+    /// Delete the chars right before and after every cursor
+    pub fn delete_around_cursors(&mut self) {
+        self.selection.clear_cursor_column();
+        let removal = self.map_each_selection_mut(|sel, text| {
+            let range = sel.cursor.backward(text).0..sel.cursor.forward(text).0;
+            *sel = sel.collapsed();
+            range
+        });
+        self.remove_ranges(removal);
+    }
+
     pub fn move_cursor<F>(&mut self, f: F)
     where
         F: Fn(Idx, &Rope) -> Idx,
@@ -687,6 +1241,111 @@ impl Buffer {
         });
     }
 
+    /// Bracket at `sel` and the one matching it
+    ///
+    /// The bracket is the char under the direction marker, or the one
+    /// right after the cursor.
+    fn bracket_pair_at(sel: Selection, text: &Rope) -> Option<(Idx, Idx)> {
+        let normalized = sel.normalized(text);
+        let marker = if normalized.is_forward() {
+            normalized.cursor.backward(text)
+        } else {
+            normalized.cursor
+        };
+
+        [marker, sel.cursor]
+            .iter()
+            .filter_map(|&idx| idx.matching_bracket(text).map(|matching| (idx, matching)))
+            .next()
+    }
+
+    /// Bracket pair at the primary selection, to highlight
+    pub fn matching_bracket_pair(&self) -> Option<(Idx, Idx)> {
+        Self::bracket_pair_at(
+            self.selection.selections[self.selection.primary],
+            &self.text,
+        )
+    }
+
+    /// Select from the bracket at each selection to the matching one
+    ///
+    /// With `extend`, only the cursor moves to the matching bracket.
+    pub fn select_matching_bracket(&mut self, extend: bool) {
+        self.selection.clear_cursor_column();
+
+        self.map_each_selection_mut(|sel, text| {
+            if let Some((bracket, matching)) = Self::bracket_pair_at(*sel, text) {
+                if matching > bracket {
+                    if !extend {
+                        sel.anchor = bracket;
+                    }
+                    sel.cursor = matching.forward(text);
+                } else {
+                    if !extend {
+                        sel.anchor = bracket.forward(text);
+                    }
+                    sel.cursor = matching;
+                }
+            }
+        });
+    }
+
+    /// Select (or extend) to the `n`-th char searched for by `find`
+    ///
+    /// Selections where the char was not found are left as they were.
+    pub fn find_char(&mut self, find: FindChar, n: usize) {
+        self.selection.clear_cursor_column();
+
+        self.map_each_selection_mut(|sel, text| {
+            let cursor = sel.cursor;
+            let new_cursor = if find.forward {
+                let start = if find.till {
+                    cursor.forward(text)
+                } else {
+                    cursor
+                };
+                start.find_char_forward(find.ch, n, text).map(|idx| {
+                    if find.till {
+                        idx
+                    } else {
+                        idx.forward(text)
+                    }
+                })
+            } else {
+                let start = if find.till {
+                    cursor.backward(text)
+                } else {
+                    cursor
+                };
+                start.find_char_backward(find.ch, n, text).map(|idx| {
+                    if find.till {
+                        idx.forward(text)
+                    } else {
+                        idx
+                    }
+                })
+            };
+
+            if let Some(new_cursor) = new_cursor {
+                if !find.extend {
+                    sel.anchor = cursor;
+                }
+                sel.cursor = new_cursor;
+            }
+        });
+    }
+
+    /// Select `object` at each selection, keeping the ones where there's none
+    pub fn select_object(&mut self, object: TextObject, around: bool) {
+        self.selection.clear_cursor_column();
+
+        self.map_each_selection_mut(|sel, text| {
+            if let Some((start, end)) = object.find(*sel, around, text) {
+                *sel = sel.unify_direction_of(Selection::new_from_normalized(start, end));
+            }
+        });
+    }
+
     pub fn expand_inner_surrounding(&mut self) {
         self.selection.clear_cursor_column();
 
@@ -728,7 +1387,9 @@ impl Buffer {
         });
     }
     pub fn cursor_coord(&self) -> Position {
-        self.selection.selections[0].cursor.to_position(&self.text)
+        self.selection.selections[self.selection.primary]
+            .cursor
+            .to_position(&self.text)
     }
 
     pub fn move_line(&mut self) {
@@ -760,11 +1421,25 @@ impl Buffer {
             anchor: Idx(0),
             cursor: Idx(self.text.len_chars()),
         }];
+        self.selection.primary = 0;
+        self.selection.clear_cursor_column();
+    }
+
+    /// Replace all the selections with a single cursor at `idx`
+    pub fn jump_to(&mut self, idx: Idx) {
+        self.selection.clear_cursor_column();
+        self.selection.selections = vec![Selection {
+            anchor: idx,
+            cursor: idx,
+        }];
+        self.selection.primary = 0;
     }
 
     pub fn collapse(&mut self) {
         if self.selection.selections.len() > 1 {
             self.selection.selections = vec![self.selection.selections[self.selection.primary]];
+            self.selection.primary = 0;
+            self.selection.clear_cursor_column();
         } else {
             self.selection.selections[self.selection.primary] =
                 self.selection.selections[self.selection.primary].collapsed();
@@ -805,8 +1480,9 @@ impl Buffer {
 
         for idx in insertions {
             self.selection.fix_on_insert(idx, text.len());
-            self.text.insert(idx.0, &text);
+            self.insert_text(idx, &text);
         }
+        self.selection.normalize();
     }
 
     fn indent_text(&self, times: usize) -> String {
@@ -839,8 +1515,722 @@ impl Buffer {
             if existing == indent_text {
                 self.selection
                     .fix_on_delete(idx, indent_text.len(), &self.text);
-                range.remove_from(&mut self.text);
+                self.remove_text(idx.0..idx.0 + indent_text.len());
+            }
+        }
+        self.selection.normalize();
+    }
+
+    /// Whitespace taking `width` visual columns, when inserted at `v_col`
+    fn padding(&self, v_col: usize, width: usize, use_tabs: bool) -> String {
+        let end = v_col + width;
+        let mut col = v_col;
+        let mut padding = String::new();
+        if use_tabs {
+            while col + distance_to_next_tabstop(col, self.tabstop) <= end {
+                col += distance_to_next_tabstop(col, self.tabstop);
+                padding.push('\t');
+            }
+        }
+        padding.push_str(&" ".repeat(end - col));
+        padding
+    }
+
+    /// Pad the selections with whitespace, so their cursors line up
+    ///
+    /// Selections are aligned by their order on their lines: the first
+    /// ones of every line together, then the second ones, and so on.
+    pub fn align_selections(&mut self) {
+        self.selection.clear_cursor_column();
+
+        for column_i in 0.. {
+            // `(selection index, visual column of the cursor)`
+            let mut to_align = vec![];
+            let mut last_line = None;
+            let mut on_line = 0;
+            for (i, sel) in self.selection.selections.iter().enumerate() {
+                let pos = sel.cursor.to_position(&self.text);
+                if last_line == Some(pos.line) {
+                    on_line += 1;
+                } else {
+                    last_line = Some(pos.line);
+                    on_line = 0;
+                }
+                if on_line == column_i {
+                    to_align.push((i, self.to_visual(pos).column));
+                }
+            }
+            if to_align.is_empty() {
+                break;
+            }
+
+            let target = to_align.iter().map(|&(_, v_col)| v_col).max().unwrap_or(0);
+            for (i, v_col) in to_align.into_iter().rev() {
+                let start = self.selection.selections[i].sorted_pair().0;
+                let start_pos = start.to_position(&self.text);
+                let use_tabs =
+                    !self.expand_tabs && start <= start.before_first_non_whitespace(&self.text);
+                let padding =
+                    self.padding(self.to_visual(start_pos).column, target - v_col, use_tabs);
+                let len = padding.chars().count();
+
+                self.insert_text(start, &padding);
+                // the padding goes before the selection, and moves all the ones after it
+                for sel in &mut self.selection.selections[i..] {
+                    sel.anchor = Idx(sel.anchor.0 + len);
+                    sel.cursor = Idx(sel.cursor.0 + len);
+                }
             }
         }
+        self.selection.normalize();
     }
+
+    /// Copy the indentation of the line of the primary selection to the
+    /// lines of the other selections
+    pub fn copy_indent(&mut self) {
+        let primary = SelectionSet {
+            selections: vec![self.selection.selections[self.selection.primary]],
+            ..default()
+        };
+        let primary_lines = primary.to_lines(&self.text);
+        let primary_line = primary.selections[0].cursor.to_position(&self.text).line;
+        let indent: String = self
+            .text
+            .line(primary_line)
+            .chars()
+            .take_while(|&ch| char::is_non_newline_whitespace(ch))
+            .collect();
+
+        let edits: Vec<_> = self
+            .selection
+            .to_lines(&self.text)
+            .difference(&primary_lines)
+            .filter_map(|&line| {
+                let content = self.text.line(line);
+                let old_len = content
+                    .chars()
+                    .take_while(|&ch| char::is_non_newline_whitespace(ch))
+                    .count();
+                // don't leave whitespace on blank lines
+                if content.chars().skip(old_len).all(char::is_newline) {
+                    return None;
+                }
+                Some((Position { line, column: 0 }.to_idx(&self.text), old_len))
+            })
+            .collect();
+
+        self.selection.clear_cursor_column();
+        for (idx, old_len) in edits.into_iter().rev() {
+            self.replace_at(idx, old_len, &indent);
+        }
+        self.selection.normalize();
+    }
+
+    /// Join the lines covered by each selection (or the line with the next one)
+    ///
+    /// The leading whitespace of the joined lines is collapsed to a single space.
+    pub fn join_lines(&mut self) {
+        let mut lines = BTreeSet::new();
+        for sel in &self.selection.selections {
+            let (from, to) = sel.sorted_pair();
+            let first = from.to_position(&self.text).line;
+            let last = max(from, to.backward(&self.text))
+                .to_position(&self.text)
+                .line;
+            lines.extend(first..max(last, first + 1));
+        }
+
+        let edits: Vec<_> = lines
+            .into_iter()
+            .filter_map(|line| {
+                if line + 1 >= self.text.len_lines() {
+                    return None;
+                }
+                let next_start = Position {
+                    line: line + 1,
+                    column: 0,
+                }
+                .to_idx(&self.text);
+                // the empty line after the final newline
+                if next_start == Idx::end(&self.text) {
+                    return None;
+                }
+                let newline = next_start.backward(&self.text);
+                let content_start =
+                    next_start.forward_while(char::is_non_newline_whitespace, &self.text);
+                let space = if content_start
+                    .next_char(&self.text)
+                    .map(char::is_newline)
+                    .unwrap_or(true)
+                {
+                    ""
+                } else {
+                    " "
+                };
+                Some((newline, content_start.0 - newline.0, space))
+            })
+            .collect();
+
+        self.selection.clear_cursor_column();
+        for (idx, len, space) in edits.into_iter().rev() {
+            self.replace_at(idx, len, space);
+        }
+        self.selection.normalize();
+    }
+
+    /// Insert `s` at `idx`, moving the selections of the other windows
+    ///
+    /// All the edits go through here and `remove_text`.
+    fn insert_text(&mut self, idx: Idx, s: &str) {
+        let len = s.chars().count();
+        for selection in self.window_selections.values_mut() {
+            selection.fix_on_insert(idx, len);
+        }
+        self.text.insert(idx.0, s);
+        self.version = next_version();
+    }
+
+    /// Remove the chars in `range`, moving the selections of the other windows
+    fn remove_text(&mut self, range: std::ops::Range<usize>) {
+        for selection in self.window_selections.values_mut() {
+            selection.fix_on_delete(Idx(range.start), range.len(), &self.text);
+            selection.normalize();
+        }
+        self.text.remove(range);
+        self.version = next_version();
+    }
+
+    /// Replace `len` chars at `idx` with `s`, keeping the selections in place
+    fn replace_at(&mut self, idx: Idx, len: usize, s: &str) {
+        if len > 0 {
+            self.selection.fix_on_delete(idx, len, &self.text);
+            self.remove_text(idx.0..idx.0 + len);
+        }
+        if !s.is_empty() {
+            self.selection.fix_on_insert(idx, s.chars().count());
+            self.insert_text(idx, s);
+        }
+    }
+
+    /// Comment out the lines touched by the selections, or uncomment them
+    /// if they all are commented out already
+    ///
+    /// Returns `false` if the file type has no comments.
+    pub fn toggle_comment(&mut self) -> bool {
+        let lines = self.selection.to_lines(&self.text);
+        let edits = if let Some(token) = self.filetype.line_comment {
+            self.line_comment_edits(&lines, token)
+        } else if let Some((open, close)) = self.filetype.block_comment {
+            self.block_comment_edits(&lines, open, close)
+        } else {
+            return false;
+        };
+
+        self.selection.clear_cursor_column();
+        // edits are sorted, so go backward to keep their indices valid
+        for (idx, len, s) in edits.into_iter().rev() {
+            self.replace_at(idx, len, &s);
+        }
+        self.selection.normalize();
+        true
+    }
+
+    /// `(idx, chars to remove, string to insert)` toggling line comments
+    ///
+    /// If some lines are not commented out, all of them get commented
+    /// (again), with the tokens aligned at the smallest indentation.
+    /// Blank lines are left alone.
+    fn line_comment_edits(
+        &self,
+        lines: &BTreeSet<usize>,
+        token: &str,
+    ) -> Vec<(Idx, usize, String)> {
+        let lines: Vec<_> = lines
+            .iter()
+            .map(|&line| {
+                let start = Position { line, column: 0 }.to_idx(&self.text);
+                let content = self.text.line(line).to_string();
+                let indent = content
+                    .chars()
+                    .take_while(|&ch| char::is_non_newline_whitespace(ch))
+                    .count();
+                (start, indent, content.trim().to_owned())
+            })
+            .filter(|(_, _, body)| !body.is_empty())
+            .collect();
+
+        if lines.iter().all(|(_, _, body)| body.starts_with(token)) {
+            lines
+                .into_iter()
+                .map(|(start, indent, body)| {
+                    let space = body[token.len()..].starts_with(' ') as usize;
+                    (
+                        Idx(start.0 + indent),
+                        token.chars().count() + space,
+                        String::new(),
+                    )
+                })
+                .collect()
+        } else {
+            let min_indent = lines
+                .iter()
+                .map(|(_, indent, _)| *indent)
+                .min()
+                .unwrap_or(0);
+            lines
+                .into_iter()
+                .map(|(start, _, _)| (Idx(start.0 + min_indent), 0, format!("{} ", token)))
+                .collect()
+        }
+    }
+
+    /// Like `line_comment_edits`, but wrapping each block of consecutive
+    /// lines in a block comment, or unwrapping it
+    fn block_comment_edits(
+        &self,
+        lines: &BTreeSet<usize>,
+        open: &str,
+        close: &str,
+    ) -> Vec<(Idx, usize, String)> {
+        let mut blocks: Vec<(usize, usize)> = vec![];
+        for &line in lines {
+            match blocks.last_mut() {
+                Some((_, last)) if *last + 1 == line => *last = line,
+                _ => blocks.push((line, line)),
+            }
+        }
+
+        let mut edits = vec![];
+        for (first, last) in blocks {
+            let start = Position {
+                line: first,
+                column: 0,
+            }
+            .to_idx(&self.text);
+            let end = Idx(self.text.line_to_char(last) + self.text.line(last).len_chars());
+            let content = self.text.slice(start.0..end.0).to_string();
+            let lead = content.chars().take_while(|ch| ch.is_whitespace()).count();
+            let body = content.trim();
+            if body.is_empty() {
+                continue;
+            }
+            let from = Idx(start.0 + lead);
+            let body_len = body.chars().count();
+            let to = Idx(from.0 + body_len);
+
+            if body.starts_with(open)
+                && body.ends_with(close)
+                && body.len() >= open.len() + close.len()
+            {
+                let open_len = open.chars().count() + body[open.len()..].starts_with(' ') as usize;
+                let close_len = close.chars().count()
+                    + body[..body.len() - close.len()].ends_with(' ') as usize;
+                if open_len + close_len >= body_len {
+                    edits.push((from, body_len, String::new()));
+                } else {
+                    edits.push((from, open_len, String::new()));
+                    edits.push((Idx(to.0 - close_len), close_len, String::new()));
+                }
+            } else {
+                edits.push((from, 0, format!("{} ", open)));
+                edits.push((to, 0, format!(" {}", close)));
+            }
+        }
+        edits
+    }
+}
+
+#[cfg(test)]
+use proptest::prelude::*;
+
+/// An edit or a move, as done by the normal and insert modes
+#[cfg(test)]
+#[derive(Debug, Clone)]
+enum BufferOp {
+    Insert(String, bool),
+    InsertTab(bool),
+    InsertEnter(bool),
+    Open,
+    Delete,
+    Yank,
+    Paste,
+    PasteExtend,
+    Backspace(bool),
+    ExpandTabs(bool),
+    ReplaceRange(usize, usize, String),
+    MoveForward(usize),
+    MoveBackward(usize),
+    MoveDown(usize),
+    ExtendForward(usize),
+    ExtendUp(usize),
+    ForwardWord,
+    BackwardWord,
+    MoveLine,
+    ExtendLine,
+    SelectInnerSurrounding,
+    ExpandInnerSurrounding,
+    ReverseSelections,
+    FindChar(FindChar, usize),
+    IncreaseIndent,
+    DecreaseIndent,
+    CycleIdentifierCase,
+    Align,
+    CopyIndent,
+    IncrementNumbers(i64),
+    JoinLines,
+    RotateContents(isize),
+    SortContents,
+}
+
+#[cfg(test)]
+fn text_strategy() -> impl Strategy<Value = String> {
+    proptest::collection::vec(
+        prop::sample::select(vec![
+            'a', 'b', '1', '-', ' ', '\t', '\n', '(', ')', '"', 'ż', '😀',
+        ]),
+        0..40,
+    )
+    .prop_map(|chars| chars.into_iter().collect())
+}
+
+#[cfg(test)]
+fn buffer_op_strategy() -> impl Strategy<Value = BufferOp> {
+    use self::BufferOp::*;
+    prop_oneof![
+        (text_strategy(), any::<bool>()).prop_map(|(s, extend)| Insert(s, extend)),
+        any::<bool>().prop_map(InsertTab),
+        any::<bool>().prop_map(InsertEnter),
+        Just(Open),
+        Just(Delete),
+        Just(Yank),
+        Just(Paste),
+        Just(PasteExtend),
+        any::<bool>().prop_map(Backspace),
+        any::<bool>().prop_map(ExpandTabs),
+        (0..50usize, 0..50usize, text_strategy()).prop_map(|(a, b, s)| ReplaceRange(a, b, s)),
+        (1..5usize).prop_map(MoveForward),
+        (1..5usize).prop_map(MoveBackward),
+        (1..3usize).prop_map(MoveDown),
+        (1..5usize).prop_map(ExtendForward),
+        (1..3usize).prop_map(ExtendUp),
+        Just(ForwardWord),
+        Just(BackwardWord),
+        Just(MoveLine),
+        Just(ExtendLine),
+        Just(SelectInnerSurrounding),
+        Just(ExpandInnerSurrounding),
+        Just(ReverseSelections),
+        (
+            prop::sample::select(vec!['a', '\n', '(']),
+            any::<(bool, bool, bool)>(),
+            1..3usize
+        )
+            .prop_map(|(ch, (forward, till, extend), n)| FindChar(
+                crate::buffer::FindChar {
+                    ch,
+                    forward,
+                    till,
+                    extend
+                },
+                n
+            )),
+        Just(IncreaseIndent),
+        Just(DecreaseIndent),
+        Just(CycleIdentifierCase),
+        Just(Align),
+        Just(CopyIndent),
+        (-3..3i64).prop_map(IncrementNumbers),
+        Just(JoinLines),
+        (-2..2isize).prop_map(RotateContents),
+        Just(SortContents),
+    ]
+}
+
+#[cfg(test)]
+fn apply_buffer_op(buffer: &mut Buffer, yanked: &mut Vec<Rope>, op: &BufferOp) {
+    use self::BufferOp::*;
+    match op {
+        Insert(s, extend) => buffer.insert(s, *extend),
+        InsertTab(extend) => buffer.insert_tab(*extend),
+        InsertEnter(extend) => buffer.insert_enter(*extend),
+        Open => buffer.open(),
+        Delete => *yanked = buffer.delete(),
+        Yank => *yanked = buffer.yank(),
+        Paste => buffer.paste(yanked),
+        PasteExtend => buffer.paste_extend(yanked),
+        Backspace(extend) => buffer.backspace(*extend),
+        ExpandTabs(expand_tabs) => buffer.expand_tabs = *expand_tabs,
+        ReplaceRange(a, b, s) => {
+            let len = buffer.text.len_chars();
+            let (a, b) = (min(*a, len), min(*b, len));
+            buffer.replace_range(Idx(min(a, b))..Idx(max(a, b)), s);
+        }
+        MoveForward(n) => buffer.move_cursor_forward(*n),
+        MoveBackward(n) => buffer.move_cursor_backward(*n),
+        MoveDown(n) => buffer.move_cursor_down(*n),
+        ExtendForward(n) => buffer.extend_cursor_forward(*n),
+        ExtendUp(n) => buffer.extend_cursor_up(*n),
+        ForwardWord => buffer.move_cursor_forward_word(),
+        BackwardWord => buffer.move_cursor_backward_word(),
+        MoveLine => buffer.move_line(),
+        ExtendLine => buffer.extend_line(),
+        SelectInnerSurrounding => buffer.select_inner_surrounding(),
+        ExpandInnerSurrounding => buffer.expand_inner_surrounding(),
+        ReverseSelections => buffer.reverse_selections(),
+        FindChar(find, n) => buffer.find_char(*find, *n),
+        IncreaseIndent => buffer.increase_indent(1),
+        DecreaseIndent => buffer.decrease_indent(1),
+        CycleIdentifierCase => buffer.cycle_identifier_case(),
+        Align => buffer.align_selections(),
+        CopyIndent => buffer.copy_indent(),
+        IncrementNumbers(delta) => buffer.increment_numbers(*delta),
+        JoinLines => buffer.join_lines(),
+        RotateContents(n) => buffer.rotate_contents(*n),
+        SortContents => buffer.sort_contents(SortBy::Numeric),
+    }
+}
+
+/// `Buffer::idx_selection_type`, the slow way
+#[cfg(test)]
+fn idx_selection_type_linear(buffer: &Buffer, idx: Idx) -> VisualSelection {
+    let selections = buffer
+        .selection
+        .selections
+        .iter()
+        .map(|sel| sel.normalized(&buffer.text));
+    if selections
+        .clone()
+        .any(|sel| sel.is_idx_inside_direction_marker(idx, &buffer.text))
+    {
+        VisualSelection::DirectionMarker
+    } else if selections
+        .clone()
+        .any(|sel| sel.is_idx_strictly_inside(idx))
+    {
+        VisualSelection::Selection
+    } else {
+        VisualSelection::None
+    }
+}
+
+/// Apply `ops` to `text` with the given selections, checking the invariants
+/// after every step, and that undo goes back through all the texts
+#[cfg(test)]
+fn check_buffer_ops(text: &str, selections: &[(usize, usize)], ops: &[BufferOp]) {
+    let mut buffer_state = crate::state::BufferState::default();
+    buffer_state.buffer = Buffer::from_text(Rope::from_str(text));
+    {
+        let buffer = &mut buffer_state.buffer;
+        let len = buffer.text.len_chars();
+        buffer.selection.selections = selections
+            .iter()
+            .map(|&(anchor, cursor)| Selection {
+                anchor: Idx(anchor % (len + 1)),
+                cursor: Idx(cursor % (len + 1)),
+            })
+            .collect();
+        buffer.selection.normalize();
+    }
+
+    let mut texts = vec![buffer_state.buffer.text.clone()];
+    // text of every version seen, which must never change
+    let mut versions = BTreeMap::new();
+    let mut check_version = |buffer: &Buffer| {
+        let text = versions
+            .entry(buffer.version())
+            .or_insert_with(|| buffer.text.clone());
+        assert_eq!(*text, buffer.text, "version {}", buffer.version());
+    };
+    let mut yanked = vec![];
+    buffer_state.maybe_commit_undo_point();
+
+    for op in ops {
+        apply_buffer_op(&mut buffer_state.buffer, &mut yanked, op);
+        buffer_state.maybe_commit_undo_point();
+
+        let buffer = &buffer_state.buffer;
+        check_version(buffer);
+        let len = buffer.text.len_chars();
+        assert!(!buffer.selection.selections.is_empty());
+        assert!(
+            buffer.selection.is_normalized(),
+            "{:?} after {:?}",
+            buffer.selection,
+            op
+        );
+        for sel in &buffer.selection.selections {
+            assert!(sel.anchor.0 <= len, "{:?} after {:?}", sel, op);
+            assert!(sel.cursor.0 <= len, "{:?} after {:?}", sel, op);
+        }
+        // overlapping selections must have been merged
+        for idx in 0..=len {
+            let overlapping = buffer
+                .selection
+                .selections
+                .iter()
+                .filter(|sel| {
+                    let (from, to) = sel.sorted_pair();
+                    from.0 <= idx && idx <= to.0
+                })
+                .count();
+            assert!(
+                overlapping <= 1,
+                "{:?} overlap at {} after {:?}",
+                buffer.selection,
+                idx,
+                op
+            );
+        }
+
+        for idx in 0..len {
+            assert_eq!(
+                buffer.idx_selection_type(Idx(idx)),
+                idx_selection_type_linear(buffer, Idx(idx))
+            );
+        }
+
+        if texts.last() != Some(&buffer.text) {
+            texts.push(buffer.text.clone());
+        }
+    }
+
+    let final_text = texts.last().cloned();
+    while let Some(text) = texts.pop() {
+        assert_eq!(buffer_state.buffer.text, text);
+        check_version(&buffer_state.buffer);
+        buffer_state.undo(1);
+    }
+    buffer_state.redo(ops.len());
+    assert_eq!(Some(buffer_state.buffer.text), final_text);
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn buffer_ops_proptest(
+        text in text_strategy(),
+        selections in proptest::collection::vec((0..50usize, 0..50usize), 1..4),
+        ops in proptest::collection::vec(buffer_op_strategy(), 0..20),
+    ) {
+        check_buffer_ops(&text, &selections, &ops);
+    }
+}
+
+#[test]
+fn insert_multibyte_regression_test() {
+    // used to move the selections by the length in bytes
+    check_buffer_ops(
+        "ab",
+        &[(0, 0), (1, 1)],
+        &[BufferOp::Insert("ż😀".into(), false)],
+    );
+}
+
+#[test]
+fn backspace_overlapping_regression_test() {
+    // used to remove the same char twice, past the end of the text
+    check_buffer_ops(
+        "",
+        &[(0, 0), (0, 0)],
+        &[
+            BufferOp::Insert("a".into(), false),
+            BufferOp::MoveLine,
+            BufferOp::Backspace(false),
+        ],
+    );
+}
+
+#[test]
+fn normalize_selections_test() {
+    let sel = |anchor, cursor| Selection {
+        anchor: Idx(anchor),
+        cursor: Idx(cursor),
+    };
+    let mut set = SelectionSet {
+        primary: 2,
+        selections: vec![
+            sel(8, 10),
+            sel(0, 2),
+            sel(6, 4),
+            sel(3, 3),
+            sel(2, 3),
+            sel(12, 12),
+        ],
+        cursor_column: vec![],
+    };
+    set.normalize();
+    assert_eq!(
+        set.selections,
+        vec![sel(0, 3), sel(6, 4), sel(8, 10), sel(12, 12)]
+    );
+    assert_eq!(set.primary, 1);
+    assert!(set.is_normalized());
+
+    set.primary = 3;
+    set.selections.push(sel(11, 12));
+    set.normalize();
+    assert_eq!(
+        set.selections,
+        vec![sel(0, 3), sel(6, 4), sel(8, 10), sel(11, 12)]
+    );
+    assert_eq!(set.primary, 3);
+}
+
+#[test]
+fn toggle_comment_test() {
+    fn toggled(path: &str, text: &str, selections: &[(usize, usize)]) -> String {
+        let mut buffer = Buffer::from_text(Rope::from_str(text));
+        buffer.filetype = filetype::from_path(Some(std::path::Path::new(path)));
+        buffer.selection.selections = selections
+            .iter()
+            .map(|&(a, c)| Selection::new_from_normalized(Idx(a), Idx(c)))
+            .collect();
+        buffer.toggle_comment();
+        buffer.text.to_string()
+    }
+
+    let text = "fn f() {\n    a();\n\n        b();\n}\n";
+    assert_eq!(
+        toggled("a.rs", text, &[(9, 32)]),
+        "fn f() {\n    // a();\n\n    //     b();\n}\n"
+    );
+    assert_eq!(
+        toggled("a.rs", "    // a();\n    //     b();\n", &[(0, 20)]),
+        "    a();\n        b();\n"
+    );
+    // mixed lines get all commented
+    assert_eq!(toggled("a.py", "#a\nb\n", &[(0, 4)]), "# #a\n# b\n");
+    // each selection toggles its own lines
+    assert_eq!(
+        toggled("a.sh", "a\nb\nc\n", &[(0, 0), (4, 4)]),
+        "# a\nb\n# c\n"
+    );
+    assert_eq!(
+        toggled("a.css", "  a;\n  b;\n", &[(0, 8)]),
+        "  /* a;\n  b; */\n"
+    );
+    assert_eq!(toggled("a.css", "  /* a; */\n", &[(3, 3)]), "  a;\n");
+    assert_eq!(toggled("a.txt", "a\n", &[(0, 0)]), "a\n");
+}
+
+#[test]
+fn sort_and_rotate_test() {
+    let mut brz = crate::headless::headless_with_text("b 10 a 9 B 9\n");
+    brz.select(&[(0, 1), (2, 4), (5, 6), (7, 8), (9, 10), (11, 12)]);
+
+    brz.keys("<a-)>");
+    assert_eq!(brz.text(), "9 b 10 a 9 B\n");
+    brz.keys("<a-(>");
+    assert_eq!(brz.text(), "b 10 a 9 B 9\n");
+
+    brz.keys(":sort<ret>");
+    assert_eq!(brz.text(), "10 9 9 B a b\n");
+    brz.keys(":sort i<ret>");
+    assert_eq!(brz.text(), "10 9 9 a B b\n");
+    brz.keys(":sort n<ret>");
+    assert_eq!(brz.text(), "9 9 10 a B b\n");
+    brz.keys(":reverse<ret>");
+    assert_eq!(brz.text(), "b B a 10 9 9\n");
+    brz.keys(":uniq<ret>");
+    assert_eq!(brz.text(), "b B a 10 9 \n");
 }
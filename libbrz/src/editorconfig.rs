@@ -0,0 +1,565 @@
+//! [EditorConfig](https://editorconfig.org) support
+//!
+//! `.editorconfig` files are looked up from the directory of a file up
+//! to the one marked with `root = true`, and read with the same handler
+//! as the files, so this works wherever the editor can read files.
+
+use ropey::Rope;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tab,
+    Space,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IndentSize {
+    Columns(usize),
+    /// Same as `tab_width`
+    Tab,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EndOfLine {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl EndOfLine {
+    fn as_str(self) -> &'static str {
+        match self {
+            EndOfLine::Lf => "\n",
+            EndOfLine::CrLf => "\r\n",
+            EndOfLine::Cr => "\r",
+        }
+    }
+}
+
+/// Properties that apply to a file
+///
+/// `None` means not set (or `unset`), and the editor defaults apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Properties {
+    pub indent_style: Option<IndentStyle>,
+    pub indent_size: Option<IndentSize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<EndOfLine>,
+    pub charset: Option<String>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+}
+
+impl Properties {
+    /// Set a property from a `name = value` pair; unknown ones are ignored
+    pub fn set(&mut self, name: &str, value: &str) {
+        let name = name.to_lowercase();
+        let value = value.to_lowercase();
+        let unset = value == "unset";
+        let as_bool = || match value.as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+
+        match name.as_str() {
+            "indent_style" => {
+                self.indent_style = match value.as_str() {
+                    "tab" => Some(IndentStyle::Tab),
+                    "space" => Some(IndentStyle::Space),
+                    _ => None,
+                }
+            }
+            "indent_size" => {
+                self.indent_size = match value.as_str() {
+                    "tab" => Some(IndentSize::Tab),
+                    _ => value
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .map(IndentSize::Columns),
+                }
+            }
+            "tab_width" => self.tab_width = value.parse().ok().filter(|&n| n > 0),
+            "end_of_line" => {
+                self.end_of_line = match value.as_str() {
+                    "lf" => Some(EndOfLine::Lf),
+                    "crlf" => Some(EndOfLine::CrLf),
+                    "cr" => Some(EndOfLine::Cr),
+                    _ => None,
+                }
+            }
+            "charset" => self.charset = if unset { None } else { Some(value.clone()) },
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace = as_bool(),
+            "insert_final_newline" => self.insert_final_newline = as_bool(),
+            _ => {}
+        }
+    }
+
+    /// Width of an indentation level, as the spec resolves it
+    pub fn indent_width(&self) -> Option<usize> {
+        match self.indent_size {
+            Some(IndentSize::Columns(n)) => Some(n),
+            Some(IndentSize::Tab) => self.tab_width,
+            None if self.indent_style == Some(IndentStyle::Tab) => self.tab_width,
+            None => None,
+        }
+    }
+
+    /// Turn the text of a file into the text to edit
+    ///
+    /// Strips the UTF-8 BOM, and uses `\n` for line endings, if the
+    /// `end_of_line` is set (`encode` brings them back).
+    pub fn decode(&self, text: Rope) -> Rope {
+        let has_bom = text.len_chars() > 0 && text.char(0) == '\u{feff}';
+        if !has_bom && self.end_of_line.unwrap_or(EndOfLine::Lf) == EndOfLine::Lf {
+            return text;
+        }
+
+        let mut s = text.to_string();
+        if has_bom {
+            s.remove(0);
+        }
+        match self.end_of_line {
+            Some(EndOfLine::CrLf) => s = s.replace("\r\n", "\n"),
+            Some(EndOfLine::Cr) => s = s.replace('\r', "\n"),
+            _ => {}
+        }
+        Rope::from_str(&s)
+    }
+
+    /// The `charset`, if it's one that `encode` can't write
+    pub fn unsupported_charset(&self) -> Option<&str> {
+        match self.charset.as_deref() {
+            None | Some("utf-8") | Some("utf-8-bom") => None,
+            charset => charset,
+        }
+    }
+
+    /// Turn the edited text into the text of the file
+    ///
+    /// Only writes UTF-8, with a BOM for `utf-8-bom`.
+    pub fn encode(&self, text: &Rope) -> Rope {
+        let bom = self.charset.as_deref() == Some("utf-8-bom");
+        let eol = match self.end_of_line {
+            None | Some(EndOfLine::Lf) if !bom => return text.clone(),
+            eol => eol.unwrap_or(EndOfLine::Lf),
+        };
+
+        let mut s = String::with_capacity(text.len_bytes() + 3);
+        if bom {
+            s.push('\u{feff}');
+        }
+        for line in text.lines() {
+            let line = line.to_string();
+            match line.strip_suffix('\n') {
+                Some(line) => {
+                    s.push_str(line.strip_suffix('\r').unwrap_or(line));
+                    s.push_str(eol.as_str());
+                }
+                None => s.push_str(&line),
+            }
+        }
+        Rope::from_str(&s)
+    }
+}
+
+/// A parsed `.editorconfig` file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EditorConfig {
+    /// Stop looking for files in the parent directories
+    pub root: bool,
+    /// `(glob, [(name, value)])`
+    pub sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl EditorConfig {
+    pub fn parse(s: &str) -> Self {
+        let mut config = Self::default();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                config
+                    .sections
+                    .push((line[1..line.len() - 1].to_owned(), vec![]));
+                continue;
+            }
+            let (name, value) = match line.find(&['=', ':'][..]) {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => continue,
+            };
+            match config.sections.last_mut() {
+                Some((_, pairs)) => pairs.push((name.to_owned(), value.to_owned())),
+                None if name.eq_ignore_ascii_case("root") => {
+                    config.root = value.eq_ignore_ascii_case("true")
+                }
+                None => {}
+            }
+        }
+        config
+    }
+
+    /// Apply the sections matching `path` (relative to the directory of
+    /// this file, with `/` separators)
+    pub fn apply(&self, path: &str, properties: &mut Properties) {
+        for (glob, pairs) in &self.sections {
+            if glob_matches(glob, path) {
+                for (name, value) in pairs {
+                    properties.set(name, value);
+                }
+            }
+        }
+    }
+}
+
+/// Properties for the file at `path`, from the `.editorconfig` files read with `read`
+pub fn properties_for(path: &Path, read: &dyn Fn(&Path) -> io::Result<Rope>) -> Properties {
+    let mut configs: Vec<(PathBuf, EditorConfig)> = vec![];
+    let mut dir = path.parent();
+    while let Some(cur) = dir {
+        if let Ok(text) = read(&cur.join(".editorconfig")) {
+            let config = EditorConfig::parse(&text.to_string());
+            let root = config.root;
+            configs.push((cur.to_owned(), config));
+            if root {
+                break;
+            }
+        }
+        dir = cur.parent();
+    }
+
+    // the closest file goes last, to override the others
+    let mut properties = Properties::default();
+    for (dir, config) in configs.iter().rev() {
+        if let Ok(relative) = path.strip_prefix(dir) {
+            let relative: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            config.apply(&relative.join("/"), &mut properties);
+        }
+    }
+    properties
+}
+
+/// A glob, after the `{...}` alternatives are expanded
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    Star,
+    /// `**`
+    DoubleStar,
+    /// `[...]`, or `[!...]` if negated
+    Class(bool, Vec<(char, char)>),
+    /// `{num1..num2}`
+    NumRange(i64, i64),
+}
+
+/// Does the section `glob` match `path` (relative to the `.editorconfig`)
+///
+/// Globs with no `/` match the file name in any directory, the other
+/// ones match the path from the directory of the `.editorconfig`.
+pub fn glob_matches(glob: &str, path: &str) -> bool {
+    let (glob, path) = if glob.contains('/') {
+        (glob.trim_start_matches('/'), path)
+    } else {
+        (glob, path.rsplit('/').next().unwrap_or(path))
+    };
+    let path: Vec<char> = path.chars().collect();
+
+    expand_braces(&glob.chars().collect::<Vec<_>>())
+        .iter()
+        .any(|glob| match_tokens(&tokenize(glob), &path))
+}
+
+/// Index of the `}` closing the `{` at `open`, and the indices of the
+/// top level `,` between them
+fn find_brace_close(glob: &[char], open: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = vec![];
+    let mut i = open + 1;
+    while i < glob.len() {
+        match glob[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some((i, commas)),
+            '}' => depth -= 1,
+            ',' if depth == 0 => commas.push(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Expand `{a,b}` alternatives into separate globs
+///
+/// `{num1..num2}` ranges are left in place, for `tokenize`. A `{` with no
+/// `,` (or no closing `}`) is a literal.
+fn expand_braces(glob: &[char]) -> Vec<Vec<char>> {
+    let mut i = 0;
+    while i < glob.len() {
+        match glob[i] {
+            '\\' => i += 1,
+            '{' => {
+                if let Some((close, commas)) = find_brace_close(glob, i) {
+                    if !commas.is_empty() {
+                        let prefix = &glob[..i];
+                        let suffix = expand_braces(&glob[close + 1..]);
+                        let bounds: Vec<_> = std::iter::once(i)
+                            .chain(commas)
+                            .chain(std::iter::once(close))
+                            .collect();
+                        let mut expanded = vec![];
+                        for w in bounds.windows(2) {
+                            for alternative in expand_braces(&glob[w[0] + 1..w[1]]) {
+                                for suffix in &suffix {
+                                    let mut glob = prefix.to_vec();
+                                    glob.extend(&alternative);
+                                    glob.extend(suffix);
+                                    expanded.push(glob);
+                                }
+                            }
+                        }
+                        return expanded;
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    vec![glob.to_vec()]
+}
+
+fn parse_num_range(s: &[char]) -> Option<(i64, i64)> {
+    let s: String = s.iter().collect();
+    let mut parts = s.splitn(2, "..");
+    let from = parts.next()?.parse().ok()?;
+    let to = parts.next()?.parse().ok()?;
+    Some((from, to))
+}
+
+fn tokenize(glob: &[char]) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < glob.len() {
+        match glob[i] {
+            '\\' if i + 1 < glob.len() => {
+                i += 1;
+                tokens.push(Token::Char(glob[i]));
+            }
+            '?' => tokens.push(Token::AnyChar),
+            '*' if glob.get(i + 1) == Some(&'*') => {
+                i += 1;
+                tokens.push(Token::DoubleStar);
+            }
+            '*' => tokens.push(Token::Star),
+            '[' => match glob[i + 1..].iter().position(|&ch| ch == ']') {
+                Some(len) => {
+                    let class = &glob[i + 1..i + 1 + len];
+                    let (negated, class) = match class.first() {
+                        Some('!') => (true, &class[1..]),
+                        _ => (false, class),
+                    };
+                    let mut ranges = vec![];
+                    let mut j = 0;
+                    while j < class.len() {
+                        if j + 2 < class.len() && class[j + 1] == '-' {
+                            ranges.push((class[j], class[j + 2]));
+                            j += 3;
+                        } else {
+                            ranges.push((class[j], class[j]));
+                            j += 1;
+                        }
+                    }
+                    tokens.push(Token::Class(negated, ranges));
+                    i += len + 1;
+                }
+                None => tokens.push(Token::Char('[')),
+            },
+            '{' => {
+                let range = glob[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == '}')
+                    .and_then(|len| Some((len, parse_num_range(&glob[i + 1..i + 1 + len])?)));
+                match range {
+                    Some((len, (from, to))) => {
+                        tokens.push(Token::NumRange(from, to));
+                        i += len + 1;
+                    }
+                    None => tokens.push(Token::Char('{')),
+                }
+            }
+            ch => tokens.push(Token::Char(ch)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+fn match_tokens(tokens: &[Token], path: &[char]) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return path.is_empty(),
+    };
+
+    match token {
+        Token::Char(ch) => path.first() == Some(ch) && match_tokens(rest, &path[1..]),
+        Token::AnyChar => {
+            matches!(path.first(), Some(&ch) if ch != '/') && match_tokens(rest, &path[1..])
+        }
+        Token::Class(negated, ranges) => match path.first() {
+            Some(&ch) => {
+                let in_class = ranges.iter().any(|&(from, to)| from <= ch && ch <= to);
+                ch != '/' && in_class != *negated && match_tokens(rest, &path[1..])
+            }
+            None => false,
+        },
+        Token::Star => {
+            let max = path.iter().position(|&ch| ch == '/').unwrap_or(path.len());
+            (0..=max).any(|n| match_tokens(rest, &path[n..]))
+        }
+        Token::DoubleStar => (0..=path.len()).any(|n| match_tokens(rest, &path[n..])),
+        Token::NumRange(from, to) => {
+            let sign = (path.first() == Some(&'-')) as usize;
+            let digits = path[sign..]
+                .iter()
+                .take_while(|ch| ch.is_ascii_digit())
+                .count();
+            (1..=digits).any(|n| {
+                let num: String = path[..sign + n].iter().collect();
+                matches!(num.parse::<i64>(), Ok(num) if *from <= num && num <= *to)
+                    && match_tokens(rest, &path[sign + n..])
+            })
+        }
+    }
+}
+
+#[test]
+fn glob_matches_test() {
+    let cases = &[
+        ("*", "a/b/c.rs", true),
+        ("*.rs", "src/main.rs", true),
+        ("*.rs", "src/main.rsx", false),
+        ("*.{js,py}", "a.py", true),
+        ("*.{js,py}", "a.rs", false),
+        ("{a,{b,c}d}.txt", "cd.txt", true),
+        ("{single}.txt", "{single}.txt", true),
+        ("lib/**.js", "lib/a/b/c.js", true),
+        ("lib/*.js", "lib/a/c.js", false),
+        ("/lib/*.js", "lib/c.js", true),
+        ("src/*.js", "lib/src/c.js", false),
+        ("a?c", "abc", true),
+        ("a[xyz]c", "ayc", true),
+        ("a[!xyz]c", "ayc", false),
+        ("a[a-c]c", "abc", true),
+        ("file{1..3}", "file2", true),
+        ("file{1..3}", "file4", false),
+        ("file{-3..-1}", "file-2", true),
+        ("\\*.txt", "*.txt", true),
+        ("\\*.txt", "a.txt", false),
+        ("Makefile", "a/Makefile", true),
+    ];
+    for &(glob, path, expected) in cases {
+        assert_eq!(glob_matches(glob, path), expected, "{} {}", glob, path);
+    }
+}
+
+#[test]
+fn properties_for_test() {
+    let read = |path: &Path| -> io::Result<Rope> {
+        match path.to_str() {
+            Some("/p/.editorconfig") => Ok(Rope::from_str(
+                "root = true\n\n[*]\nindent_style = space\nindent_size = 4\n\
+                 insert_final_newline = true\n\n[Makefile]\nindent_style = tab\n",
+            )),
+            Some("/p/web/.editorconfig") => Ok(Rope::from_str(
+                "# web stuff\n[*.{js,css}]\nindent_size = 2\nend_of_line = crlf\n\
+                 [lib/**]\ninsert_final_newline = unset\n",
+            )),
+            Some("/.editorconfig") => Ok(Rope::from_str("[*]\ncharset = latin1\n")),
+            _ => Err(io::ErrorKind::NotFound.into()),
+        }
+    };
+
+    let props = properties_for(Path::new("/p/web/lib/a.js"), &read);
+    assert_eq!(props.indent_style, Some(IndentStyle::Space));
+    assert_eq!(props.indent_width(), Some(2));
+    assert_eq!(props.end_of_line, Some(EndOfLine::CrLf));
+    assert_eq!(props.insert_final_newline, None);
+    // above the root
+    assert_eq!(props.charset, None);
+
+    let props = properties_for(Path::new("/p/Makefile"), &read);
+    assert_eq!(props.indent_style, Some(IndentStyle::Tab));
+    assert_eq!(props.indent_width(), Some(4));
+    assert_eq!(props.insert_final_newline, Some(true));
+}
+
+#[test]
+fn encode_decode_test() {
+    let props = Properties {
+        end_of_line: Some(EndOfLine::CrLf),
+        charset: Some("utf-8-bom".into()),
+        ..Properties::default()
+    };
+    let text = props.decode(Rope::from_str("\u{feff}a\r\nb"));
+    assert_eq!(text.to_string(), "a\nb");
+    assert_eq!(props.encode(&text).to_string(), "\u{feff}a\r\nb");
+}
+
+#[test]
+fn editorconfig_test() {
+    let mut brz = crate::headless::headless_with_files(&[
+        (
+            ".editorconfig",
+            "root = true\n[*.txt]\nindent_style = tab\ntab_width = 3\nend_of_line = crlf\n\
+             trim_trailing_whitespace = true\ninsert_final_newline = true\n",
+        ),
+        ("other.txt", "a  \r\n  b\r\nc"),
+    ]);
+    brz.state.open_buffer(Path::new("other.txt"));
+    assert_eq!(brz.text(), "a  \n  b\nc");
+    assert!(brz.snapshot().contains("text tabs:3"));
+
+    brz.keys(":w<ret>");
+    assert_eq!(brz.text(), "a\n  b\nc\n");
+    assert_eq!(brz.file("other.txt").unwrap(), "a\r\n  b\r\nc\r\n");
+    assert!(!brz.state.cur_buffer_state().is_modified());
+
+    brz.keys("u");
+    assert_eq!(brz.text(), "a  \n  b\nc");
+
+    // nothing changes if it can't be written
+    brz.state
+        .register_write_handler(|_, _| Err(std::io::ErrorKind::PermissionDenied.into()));
+    brz.keys(":w<ret>");
+    assert_eq!(brz.text(), "a  \n  b\nc");
+    assert!(brz.state.msg.is_some());
+    brz.keys("u");
+    assert_eq!(brz.text(), "a  \n  b\nc");
+}
+
+#[test]
+fn unsupported_charset_test() {
+    let mut brz = crate::headless::headless_with_files(&[
+        (".editorconfig", "[*]\ncharset = latin1\n"),
+        ("a.txt", "zażółć\n"),
+    ]);
+    brz.state.open_buffer(Path::new("a.txt"));
+    brz.keys("d:w<ret>");
+    assert_eq!(brz.file("a.txt").unwrap(), "ażółć\n");
+    assert_eq!(
+        brz.state.msg.as_deref(),
+        Some("Unsupported charset latin1, written as UTF-8")
+    );
+}
@@ -0,0 +1,229 @@
+//! File types, and what the editor should know about each
+//!
+//! Picked by the extension of the buffer path.
+
+use std::path::Path;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FileType {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    /// Whole file names, for files without an extension
+    pub file_names: &'static [&'static str],
+    /// Opening chars that get their closer inserted automatically
+    pub auto_pairs: &'static [char],
+    /// Token starting a comment that lasts until the end of line
+    pub line_comment: Option<&'static str>,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    /// Overrides the indentation detected in the file
+    pub expand_tabs: Option<bool>,
+    pub indent_width: Option<usize>,
+    /// Command reading the file from stdin and printing it formatted;
+    /// `%` is replaced with the path of the file
+    pub formatter: Option<&'static [&'static str]>,
+}
+
+const DEFAULT_PAIRS: &[char] = &['(', '[', '{', '"', '\''];
+/// `'` is used on its own a lot, eg. for lifetimes or in prose
+const NO_QUOTE_PAIRS: &[char] = &['(', '[', '{', '"'];
+
+pub const PLAIN: FileType = FileType {
+    name: "text",
+    extensions: &["txt", "md"],
+    file_names: &[],
+    auto_pairs: NO_QUOTE_PAIRS,
+    line_comment: None,
+    block_comment: None,
+    expand_tabs: None,
+    indent_width: None,
+    formatter: None,
+};
+
+pub static FILE_TYPES: &[FileType] = &[
+    FileType {
+        name: "rust",
+        extensions: &["rs"],
+        file_names: &[],
+        auto_pairs: NO_QUOTE_PAIRS,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        expand_tabs: None,
+        indent_width: None,
+        formatter: Some(&["rustfmt", "--emit", "stdout"]),
+    },
+    FileType {
+        name: "c",
+        extensions: &["c", "h", "cc", "cpp", "hpp"],
+        file_names: &[],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        expand_tabs: None,
+        indent_width: None,
+        formatter: None,
+    },
+    FileType {
+        name: "javascript",
+        extensions: &["js", "ts", "json"],
+        file_names: &[],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        expand_tabs: None,
+        indent_width: None,
+        formatter: Some(&["prettier", "--stdin-filepath", "%"]),
+    },
+    FileType {
+        name: "go",
+        extensions: &["go"],
+        file_names: &[],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        expand_tabs: Some(false),
+        indent_width: None,
+        formatter: Some(&["gofmt"]),
+    },
+    FileType {
+        name: "python",
+        extensions: &["py"],
+        file_names: &[],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: Some("#"),
+        block_comment: None,
+        expand_tabs: Some(true),
+        indent_width: None,
+        formatter: None,
+    },
+    FileType {
+        name: "shell",
+        extensions: &["sh", "bash"],
+        file_names: &[],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: Some("#"),
+        block_comment: None,
+        expand_tabs: None,
+        indent_width: None,
+        formatter: None,
+    },
+    FileType {
+        name: "toml",
+        extensions: &["toml"],
+        file_names: &[],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: Some("#"),
+        block_comment: None,
+        expand_tabs: None,
+        indent_width: None,
+        formatter: None,
+    },
+    FileType {
+        name: "yaml",
+        extensions: &["yml", "yaml"],
+        file_names: &[],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: Some("#"),
+        block_comment: None,
+        expand_tabs: Some(true),
+        indent_width: None,
+        formatter: None,
+    },
+    FileType {
+        name: "haskell",
+        extensions: &["hs"],
+        file_names: &[],
+        auto_pairs: NO_QUOTE_PAIRS,
+        line_comment: Some("--"),
+        block_comment: Some(("{-", "-}")),
+        expand_tabs: Some(true),
+        indent_width: None,
+        formatter: None,
+    },
+    FileType {
+        name: "lua",
+        extensions: &["lua"],
+        file_names: &[],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: Some("--"),
+        block_comment: Some(("--[[", "]]")),
+        expand_tabs: None,
+        indent_width: None,
+        formatter: None,
+    },
+    FileType {
+        name: "sql",
+        extensions: &["sql"],
+        file_names: &[],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: Some("--"),
+        block_comment: Some(("/*", "*/")),
+        expand_tabs: None,
+        indent_width: None,
+        formatter: None,
+    },
+    FileType {
+        name: "css",
+        extensions: &["css"],
+        file_names: &[],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: None,
+        block_comment: Some(("/*", "*/")),
+        expand_tabs: None,
+        indent_width: None,
+        formatter: Some(&["prettier", "--stdin-filepath", "%"]),
+    },
+    FileType {
+        name: "lisp",
+        extensions: &["lisp", "el", "clj", "scm"],
+        file_names: &[],
+        auto_pairs: NO_QUOTE_PAIRS,
+        line_comment: Some(";"),
+        block_comment: None,
+        expand_tabs: None,
+        indent_width: None,
+        formatter: None,
+    },
+    FileType {
+        name: "make",
+        extensions: &["mk"],
+        file_names: &["Makefile", "makefile", "GNUmakefile"],
+        auto_pairs: DEFAULT_PAIRS,
+        line_comment: Some("#"),
+        block_comment: None,
+        // recipes must be indented with tabs
+        expand_tabs: Some(false),
+        indent_width: None,
+        formatter: None,
+    },
+    PLAIN,
+];
+
+/// File type of a buffer with the given path
+pub fn from_path(path: Option<&Path>) -> &'static FileType {
+    let by_name = path
+        .and_then(|path| path.file_name())
+        .and_then(|name| name.to_str())
+        .and_then(|name| FILE_TYPES.iter().find(|ft| ft.file_names.contains(&name)));
+
+    by_name
+        .or_else(|| {
+            path.and_then(|path| path.extension())
+                .and_then(|ext| ext.to_str())
+                .and_then(|ext| {
+                    FILE_TYPES
+                        .iter()
+                        .find(|ft| ft.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+                })
+        })
+        .unwrap_or(&PLAIN)
+}
+
+#[test]
+fn from_path_test() {
+    assert_eq!(from_path(Some(Path::new("src/main.rs"))).name, "rust");
+    assert_eq!(from_path(Some(Path::new("a/b.PY"))).name, "python");
+    assert_eq!(from_path(Some(Path::new("src/Makefile"))).name, "make");
+    assert_eq!(from_path(Some(Path::new("LICENSE"))).name, "text");
+    assert_eq!(from_path(None).name, "text");
+    assert_eq!(from_path(Some(Path::new("a.css"))).line_comment, None);
+}
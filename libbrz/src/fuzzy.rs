@@ -0,0 +1,189 @@
+//! Fuzzy matching
+//!
+//! Scoring is a simplified version of what `fzf` does: every matched char
+//! is worth some points, with bonuses for matching at the start of path
+//! components and words, for runs of consecutive chars and for matching
+//! the case exactly, and penalties for gaps between matched chars.
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_PATH_SEPARATOR: i64 = 32;
+const BONUS_WORD_BOUNDARY: i64 = 24;
+const BONUS_CAMEL_CASE: i64 = 16;
+const BONUS_CONSECUTIVE: i64 = 16;
+const BONUS_CASE: i64 = 2;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Match {
+    pub score: i64,
+    /// Indices (in chars, not bytes) of the candidate chars matched by the pattern
+    pub positions: Vec<usize>,
+}
+
+fn is_path_separator(ch: char) -> bool {
+    ch == '/' || ch == '\\'
+}
+
+fn chars_match(pattern_ch: char, ch: char) -> bool {
+    pattern_ch == ch || pattern_ch.to_lowercase().eq(ch.to_lowercase())
+}
+
+/// Bonus for matching `candidate[i]`, depending on where in the candidate it is
+fn position_bonus(candidate: &[char], i: usize) -> i64 {
+    if i == 0 {
+        return BONUS_PATH_SEPARATOR;
+    }
+
+    let prev = candidate[i - 1];
+    let cur = candidate[i];
+
+    if is_path_separator(prev) {
+        BONUS_PATH_SEPARATOR
+    } else if !prev.is_alphanumeric() && cur.is_alphanumeric() {
+        BONUS_WORD_BOUNDARY
+    } else if prev.is_lowercase() && cur.is_uppercase() {
+        BONUS_CAMEL_CASE
+    } else {
+        0
+    }
+}
+
+/// Score `candidate` against `pattern`
+///
+/// Returns `None` if `pattern` is not a (case insensitive) subsequence
+/// of the `candidate`. Out of all the ways the pattern can be matched,
+/// the one with the highest score is picked.
+pub fn score(pattern: &str, candidate: &str) -> Option<Match> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let candidate: Vec<char> = candidate.chars().collect();
+
+    if pattern.is_empty() {
+        return Some(Match::default());
+    }
+
+    let m = pattern.len();
+    let n = candidate.len();
+
+    if n < m {
+        return None;
+    }
+
+    // `best[i * n + j]`: the best score for matching `pattern[..=i]`,
+    // with `pattern[i]` matched at `candidate[j]`
+    let mut best: Vec<Option<i64>> = vec![None; m * n];
+    // bonus of the first char in the consecutive run ending at `(i, j)`
+    let mut run_bonus: Vec<i64> = vec![0; m * n];
+    // `j` of `pattern[i - 1]` in the best match ending at `(i, j)`
+    let mut back: Vec<usize> = vec![0; m * n];
+
+    for i in 0..m {
+        // best `(best[i - 1][k] + k * PENALTY_GAP_EXTENSION, k)` for all `k < j - 1`
+        let mut best_gapped: Option<(i64, usize)> = None;
+
+        for j in i..n {
+            if i > 0 && j >= 2 {
+                let k = j - 2;
+                if let Some(score) = best[(i - 1) * n + k] {
+                    let score = score + k as i64 * PENALTY_GAP_EXTENSION;
+                    if best_gapped.map(|(s, _)| s < score).unwrap_or(true) {
+                        best_gapped = Some((score, k));
+                    }
+                }
+            }
+
+            if !chars_match(pattern[i], candidate[j]) {
+                continue;
+            }
+
+            let bonus = position_bonus(&candidate, j);
+            let case_bonus = if pattern[i] == candidate[j] {
+                BONUS_CASE
+            } else {
+                0
+            };
+
+            // `(score so far, j of the previous char, bonus for this char, bonus of the run)`
+            let (prev_score, prev_j, char_bonus, cur_run_bonus) = if i == 0 {
+                (0, 0, bonus, bonus)
+            } else {
+                let consecutive = best[(i - 1) * n + j - 1].map(|score| {
+                    let prev_run_bonus = run_bonus[(i - 1) * n + j - 1];
+                    (
+                        score,
+                        j - 1,
+                        std::cmp::max(BONUS_CONSECUTIVE, prev_run_bonus),
+                        prev_run_bonus,
+                    )
+                });
+                let gapped = best_gapped.map(|(score, k)| {
+                    (
+                        score
+                            - (j - 1) as i64 * PENALTY_GAP_EXTENSION
+                            - (PENALTY_GAP_START - PENALTY_GAP_EXTENSION),
+                        k,
+                        bonus,
+                        bonus,
+                    )
+                });
+
+                match (consecutive, gapped) {
+                    (Some(c), Some(g)) => {
+                        if g.0 + g.2 > c.0 + c.2 {
+                            g
+                        } else {
+                            c
+                        }
+                    }
+                    (Some(c), None) => c,
+                    (None, Some(g)) => g,
+                    (None, None) => continue,
+                }
+            };
+
+            let i_j = i * n + j;
+            best[i_j] = Some(prev_score + SCORE_MATCH + char_bonus + case_bonus);
+            run_bonus[i_j] = cur_run_bonus;
+            back[i_j] = prev_j;
+        }
+    }
+
+    let (score, mut j) = (m - 1..n)
+        .filter_map(|j| best[(m - 1) * n + j].map(|score| (score, j)))
+        .max_by_key(|&(score, j)| (score, std::cmp::Reverse(j)))?;
+
+    let mut positions = vec![0; m];
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = back[i * n + j];
+    }
+
+    Some(Match { score, positions })
+}
+
+#[test]
+fn score_positions_test() {
+    assert_eq!(score("abc", "xaxbxc").unwrap().positions, vec![1, 3, 5]);
+    assert_eq!(score("abc", "xabcx").unwrap().positions, vec![1, 2, 3]);
+    assert_eq!(score("rs", "src/main.rs").unwrap().positions, vec![9, 10]);
+    assert_eq!(score("acb", "abc"), None);
+    assert_eq!(score("", "abc"), Some(Match::default()));
+}
+
+#[test]
+fn score_ranking_test() {
+    let s = |pattern, candidate| score(pattern, candidate).unwrap().score;
+
+    // consecutive
+    assert!(s("abc", "foo/abc.rs") > s("abc", "foo/axbxc.rs"));
+    // path component start
+    assert!(s("m", "src/mode.rs") > s("m", "src/ramen.rs"));
+    // word and camel case boundary
+    assert!(s("b", "foo_bar") > s("b", "foobar"));
+    assert!(s("b", "fooBar") > s("b", "foobar"));
+    // exact case
+    assert!(s("Foo", "Foo.rs") > s("Foo", "foo.rs"));
+    // a consecutive run in one component is better than
+    // the same chars scattered over many components
+    assert!(s("foo", "src/foo.rs") > s("foo", "f/o/o"));
+}
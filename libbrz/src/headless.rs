@@ -0,0 +1,228 @@
+//! Headless editor, driven by key scripts
+//!
+//! Useful for end-to-end tests: feed a script like `xd3jp<esc>` into the
+//! `State`, and check the text, selections or a snapshot of the screen.
+//!
+//! In a key script every char is a key, except `<...>`, that names one
+//! (`<esc>`, `<ret>`, `<a-x>`, `<c-p>`, `<lt>`... - see `Key::from_str`).
+//! Newlines are ignored, so long scripts can be split into lines.
+
+use crate::render::{default_color_map, Coord, Frame, FrameRenderer};
+use crate::selection::Selection;
+use crate::state::State;
+use crate::{Idx, Key};
+
+/// Parse a key script into keys
+pub fn parse_keys(script: &str) -> Result<Vec<Key>, String> {
+    let mut keys = vec![];
+    let mut chars = script.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\n' | '\r' => {}
+            '<' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('>') => break,
+                        Some(ch) => name.push(ch),
+                        None => return Err(format!("unterminated key name: <{}", name)),
+                    }
+                }
+                keys.push(name.parse()?);
+            }
+            ch => keys.push(Key::Char(ch)),
+        }
+    }
+
+    Ok(keys)
+}
+
+pub struct Headless {
+    pub state: State,
+    dimensions: Coord,
+}
+
+impl Headless {
+    pub fn new(state: State, dimensions: Coord) -> Self {
+        Self { state, dimensions }
+    }
+
+    /// Feed a key script
+    ///
+    /// Panics if the script is invalid, as it's meant for tests.
+    pub fn keys(&mut self, script: &str) -> &mut Self {
+        let keys = parse_keys(script).unwrap_or_else(|e| panic!("invalid key script: {}", e));
+        self.feed(&keys)
+    }
+
+    /// Feed keys, until the editor finishes
+    pub fn feed(&mut self, keys: &[Key]) -> &mut Self {
+        for key in keys {
+            if self.state.is_finished() {
+                break;
+            }
+            self.state.handle_key(*key);
+        }
+        self
+    }
+
+    /// Text of the current buffer
+    pub fn text(&self) -> String {
+        self.state
+            .cur_buffer_opt()
+            .map(|buffer| buffer.text.to_string())
+            .unwrap_or_default()
+    }
+
+    /// `(anchor, cursor)` char indices of the selections of the current buffer
+    pub fn selections(&self) -> Vec<(usize, usize)> {
+        self.state
+            .cur_buffer_opt()
+            .map(|buffer| {
+                buffer
+                    .selection
+                    .selections
+                    .iter()
+                    .map(|sel| (sel.anchor.0, sel.cursor.0))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Set the selections of the current buffer to `(anchor, cursor)` char
+    /// indices, the first one primary
+    pub fn select(&mut self, selections: &[(usize, usize)]) -> &mut Self {
+        let selection = &mut self.state.cur_buffer_mut().selection;
+        selection.selections = selections
+            .iter()
+            .map(|&(anchor, cursor)| Selection::new_from_normalized(Idx(anchor), Idx(cursor)))
+            .collect();
+        selection.primary = 0;
+        self
+    }
+
+    pub fn frame(&self) -> Frame {
+        let mut render = FrameRenderer::new(self.dimensions, default_color_map());
+        self.state.render(&mut render);
+        render.frame
+    }
+
+    /// The screen as text, with trailing whitespace trimmed
+    pub fn snapshot(&self) -> String {
+        let frame = self.frame();
+        (0..frame.dimensions.y)
+            .map(|y| frame.row_text(y).trim_end().to_owned())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Headless editor with an in-memory file system holding `files`
+/// (`(path, text)`) and clipboard, and nothing opened yet
+///
+/// A path only needs to match the end of the one being read, so eg.
+/// `.editorconfig` is found in any directory. Written files can be read
+/// back with `Headless::file`.
+#[cfg(test)]
+pub(crate) fn headless_with_files(files: &[(&str, &str)]) -> Headless {
+    use std::{cell::RefCell, collections::BTreeMap, path::PathBuf, rc::Rc};
+
+    let files: Rc<RefCell<BTreeMap<PathBuf, String>>> = Rc::new(RefCell::new(
+        files
+            .iter()
+            .map(|&(path, text)| (PathBuf::from(path), text.to_owned()))
+            .collect(),
+    ));
+    let mut state = State::default();
+
+    let read_files = files.clone();
+    state.register_read_handler(move |path| {
+        let files = read_files.borrow();
+        files
+            .get(path)
+            .or_else(|| {
+                files
+                    .iter()
+                    .find(|(file_path, _)| path.ends_with(file_path))
+                    .map(|(_, text)| text)
+            })
+            .map(|text| ropey::Rope::from_str(text))
+            .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    });
+    state.register_write_handler(move |path, rope| {
+        files.borrow_mut().insert(path.to_owned(), rope.to_string());
+        Ok(())
+    });
+
+    let clipboard = Rc::new(RefCell::new(String::new()));
+    let (copy, paste) = (clipboard.clone(), clipboard);
+    state.register_clipboard_handler(
+        move |text| {
+            *copy.borrow_mut() = text.to_owned();
+            Ok(())
+        },
+        move || Ok(paste.borrow().clone()),
+    );
+
+    Headless::new(state, Coord { x: 40, y: 10 })
+}
+
+#[cfg(test)]
+pub(crate) fn headless_with_text(text: &str) -> Headless {
+    let mut brz = headless_with_files(&[("test.txt", text)]);
+    brz.state.open_buffer(std::path::Path::new("test.txt"));
+    brz
+}
+
+#[cfg(test)]
+impl Headless {
+    /// Text of a file in the file system of `headless_with_files`
+    pub(crate) fn file(&self, path: &str) -> Option<String> {
+        (self.state.read_handler)(std::path::Path::new(path))
+            .ok()
+            .map(|rope| rope.to_string())
+    }
+
+    pub(crate) fn clipboard(&self) -> String {
+        (self.state.clipboard_paste_handler)().expect("clipboard")
+    }
+
+    pub(crate) fn set_clipboard(&self, text: &str) {
+        (self.state.clipboard_copy_handler)(text).expect("clipboard");
+    }
+}
+
+#[test]
+fn parse_keys_test() {
+    assert_eq!(
+        parse_keys("x<a-x>\n<c-p><esc><lt>"),
+        Ok(vec![
+            Key::Char('x'),
+            Key::Alt('x'),
+            Key::Ctrl('p'),
+            Key::Esc,
+            Key::Char('<'),
+        ])
+    );
+    assert!(parse_keys("<esc").is_err());
+    assert!(parse_keys("<nope>").is_err());
+}
+
+#[test]
+fn headless_edit_test() {
+    let mut brz = headless_with_text("one\ntwo\nthree\n");
+
+    brz.keys("xd");
+    assert_eq!(brz.text(), "two\nthree\n");
+
+    brz.keys("ihey <esc>");
+    assert_eq!(brz.text(), "hey two\nthree\n");
+    assert_eq!(brz.state.mode_name(), "normal");
+
+    assert!(brz.snapshot().contains("hey two"));
+
+    brz.keys(":q<ret>i");
+    assert!(brz.state.is_finished());
+    assert_eq!(brz.text(), "hey two\nthree\n");
+}
@@ -37,7 +37,7 @@ fn is_indent_closing_char(ch: char) -> bool {
     }
 }
 
-fn matching_char(ch: char) -> char {
+pub(crate) fn matching_char(ch: char) -> char {
     match ch {
         '{' => '}',
         '}' => '{',
@@ -307,6 +307,103 @@ impl Idx {
         cur
     }
 
+    /// Index of the bracket matching the one at `self`
+    pub fn matching_bracket(self, text: &Rope) -> Option<Idx> {
+        let ch = self.next_char(text)?;
+        let matching = matching_char(ch);
+        let mut depth = 0;
+        let mut nesting = |c: char| {
+            if c == ch {
+                depth += 1;
+            } else if c == matching {
+                if depth == 0 {
+                    return false;
+                }
+                depth -= 1;
+            }
+            true
+        };
+
+        if char::is_opening_indent(ch) {
+            let idx = self.forward(text).forward_while(&mut nesting, text);
+            if idx.next_char(text) == Some(matching) {
+                return Some(idx);
+            }
+        } else if char::is_closing_indent(ch) {
+            let idx = self.backward_while(&mut nesting, text);
+            if idx.prev_char(text) == Some(matching) {
+                return Some(idx.backward(text));
+            }
+        }
+        None
+    }
+
+    /// `(start, end)` of the number literal at, or after `self` on the same line
+    ///
+    /// See `util::number` for the literals.
+    pub fn number_literal_at_or_after(self, text: &Rope) -> Option<(Idx, Idx)> {
+        let word_start = self.backward_while(|ch| ch.is_ascii_alphanumeric(), text);
+        let first_digit = word_start.forward_while(|ch| !ch.is_ascii_digit() && ch != '\n', text);
+        if !first_digit.next_char(text)?.is_ascii_digit() {
+            return None;
+        }
+
+        let radix = match (
+            first_digit.next_char(text),
+            first_digit.forward(text).next_char(text),
+        ) {
+            (Some('0'), Some('x')) | (Some('0'), Some('X')) => 16,
+            (Some('0'), Some('b')) | (Some('0'), Some('B')) => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            let digits_start = first_digit.forward_n(2, text);
+            let end = digits_start.forward_while(|ch| ch.is_digit(radix), text);
+            if end != digits_start {
+                return Some((first_digit, end));
+            }
+        }
+
+        let end = first_digit.forward_while(|ch| ch.is_ascii_digit(), text);
+        let minus = first_digit.backward(text);
+        // `a-1` is a subtraction, not a negative number
+        if first_digit.prev_char(text) == Some('-')
+            && !matches!(minus.prev_char(text), Some(ch) if char::is_word_forming(ch))
+        {
+            Some((minus, end))
+        } else {
+            Some((first_digit, end))
+        }
+    }
+
+    /// Index of the `n`-th `ch` at, or after `self`
+    pub fn find_char_forward(self, ch: char, n: usize, text: &Rope) -> Option<Idx> {
+        let mut cur = self;
+        for i in 1..=n {
+            cur = cur.forward_while(|c| c != ch, text);
+            if cur == Self::end(text) {
+                return None;
+            }
+            if i < n {
+                cur = cur.forward(text);
+            }
+        }
+        Some(cur)
+    }
+
+    /// Index of the `n`-th `ch` before `self`
+    pub fn find_char_backward(self, ch: char, n: usize, text: &Rope) -> Option<Idx> {
+        let mut cur = self;
+        for _ in 0..n {
+            cur = cur.backward_while(|c| c != ch, text);
+            if cur == Self::begining(text) {
+                return None;
+            }
+            cur = cur.backward(text);
+        }
+        Some(cur)
+    }
+
     pub fn forward_to_line_end(self, text: &Rope) -> Idx {
         self.forward_while(char::is_not_newline, text)
     }
@@ -384,3 +481,35 @@ impl From<Idx> for usize {
         idx.0
     }
 }
+
+#[test]
+fn matching_bracket_test() {
+    let text = Rope::from_str("f(a[b]c) ((x))\n");
+    assert_eq!(Idx(1).matching_bracket(&text), Some(Idx(7)));
+    assert_eq!(Idx(7).matching_bracket(&text), Some(Idx(1)));
+    assert_eq!(Idx(3).matching_bracket(&text), Some(Idx(5)));
+    assert_eq!(Idx(9).matching_bracket(&text), Some(Idx(13)));
+    assert_eq!(Idx(12).matching_bracket(&text), Some(Idx(10)));
+    assert_eq!(Idx(2).matching_bracket(&text), None);
+
+    let mut brz = crate::headless::headless_with_text("f(a[b]c)\n");
+
+    brz.keys("lm");
+    assert_eq!(brz.selections(), vec![(1, 8)]);
+    brz.keys("m");
+    assert_eq!(brz.selections(), vec![(8, 1)]);
+    brz.keys("<space>llM");
+    assert_eq!(brz.selections(), vec![(2, 6)]);
+
+    // the pair at the primary selection is highlighted
+    let frame = brz.frame();
+    let fg_at = |x| {
+        frame
+            .get(crate::render::Coord { x, y: 0 })
+            .map(|(_, style)| style.fg)
+    };
+    let matching_bracket = crate::render::default_color_map().matching_bracket.fg;
+    assert_eq!(fg_at(2 + 3), Some(matching_bracket));
+    assert_eq!(fg_at(2 + 5), Some(matching_bracket));
+    assert_ne!(fg_at(2 + 1), Some(matching_bracket));
+}
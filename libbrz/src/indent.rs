@@ -0,0 +1,109 @@
+//! Guessing the indentation style of a text
+
+use ropey::Rope;
+use std::cmp::{max, min};
+
+/// Lines to look at; the start of a file is usually representative
+const MAX_LINES: usize = 1000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Indent {
+    pub expand_tabs: bool,
+    /// Width of an indentation level, if it could be guessed
+    pub width: Option<usize>,
+}
+
+/// Guess the indentation of `text` from the leading whitespace of its lines
+///
+/// Returns `None` if there are no indented lines. The width is the most
+/// common change of the indentation between consecutive lines.
+pub fn detect(text: &Rope) -> Option<Indent> {
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    // how many times the indentation changed by `i` spaces
+    let mut deltas = [0usize; 9];
+    let mut prev_spaces = 0;
+
+    for line in text.lines().take(MAX_LINES) {
+        if line.chars().next() == Some('\t') {
+            tab_lines += 1;
+            continue;
+        }
+        let spaces = line.chars().take_while(|&ch| ch == ' ').count();
+        if line.chars().skip(spaces).all(char::is_whitespace) {
+            // blank lines say nothing
+            continue;
+        }
+        if spaces > 0 {
+            space_lines += 1;
+        }
+        let delta = max(spaces, prev_spaces) - min(spaces, prev_spaces);
+        // 1 is usually alignment, eg. ` * ` in block comments
+        if 2 <= delta && delta < deltas.len() {
+            deltas[delta] += 1;
+        }
+        prev_spaces = spaces;
+    }
+
+    if tab_lines == 0 && space_lines == 0 {
+        return None;
+    }
+    if tab_lines > space_lines {
+        return Some(Indent {
+            expand_tabs: false,
+            width: None,
+        });
+    }
+
+    let width = (2..deltas.len())
+        .filter(|&w| deltas[w] > 0)
+        // the smaller one on ties
+        .max_by_key(|&w| (deltas[w], std::cmp::Reverse(w)));
+    Some(Indent {
+        expand_tabs: true,
+        width,
+    })
+}
+
+#[test]
+fn detect_test() {
+    let detect_str = |s: &str| detect(&Rope::from_str(s));
+
+    assert_eq!(detect_str("a\nb\n"), None);
+    assert_eq!(
+        detect_str("all:\n\tcc a.c\n\tcc b.c\n"),
+        Some(Indent {
+            expand_tabs: false,
+            width: None
+        })
+    );
+    assert_eq!(
+        detect_str("a:\n  b:\n    c: 1\n\n    d: 2\n  e: 3\n"),
+        Some(Indent {
+            expand_tabs: true,
+            width: Some(2)
+        })
+    );
+    assert_eq!(
+        detect_str("fn f() {\n    /*\n     * x\n     */\n    if a {\n        b();\n    }\n}\n"),
+        Some(Indent {
+            expand_tabs: true,
+            width: Some(4)
+        })
+    );
+}
+
+#[test]
+fn detect_indent_test() {
+    let mut brz = crate::headless::headless_with_files(&[
+        ("test.txt", "a:\n  b: 1\n"),
+        ("Makefile", "all:\n    cc a.c\n"),
+    ]);
+    brz.state.open_buffer(std::path::Path::new("test.txt"));
+    assert!(brz.snapshot().contains("text spaces:2"));
+
+    // the file type overrides the guess
+    brz.state.open_buffer(std::path::Path::new("Makefile"));
+    assert!(!brz.state.cur_buffer().expand_tabs);
+    assert!(brz.snapshot().contains("make tabs:4"));
+}
@@ -2,16 +2,28 @@
 
 pub mod action;
 pub mod buffer;
+pub mod editorconfig;
+pub mod filetype;
+pub mod fuzzy;
+pub mod headless;
 pub mod idx;
+pub mod indent;
 pub mod mode;
+pub mod plugin;
 pub mod position;
 pub mod range;
 pub mod selection;
 
 pub mod prelude;
+pub mod protocol;
 pub mod render;
+pub mod rpc;
+pub mod server;
 pub mod state;
+pub mod symbol;
+pub mod text_object;
 pub mod util;
+pub mod window;
 
 pub use self::idx::Idx;
 pub use self::mode::Mode;
@@ -82,6 +94,58 @@ impl fmt::Display for Key {
     }
 }
 
+impl std::str::FromStr for Key {
+    type Err = String;
+
+    /// Parse a key name, like the ones `Display` gives (`x`, `a-x`, `c-x`, `esc`, `f1`...)
+    fn from_str(s: &str) -> Result<Self, String> {
+        use self::Key::*;
+        let err = || format!("unknown key: {}", s);
+
+        if let Some(rest) = s.strip_prefix("a-") {
+            return match rest.parse() {
+                Ok(Char(c)) => Ok(Alt(c)),
+                _ => Err(err()),
+            };
+        }
+        if let Some(rest) = s.strip_prefix("c-") {
+            return match rest.parse() {
+                Ok(Char(c)) => Ok(Ctrl(c)),
+                _ => Err(err()),
+            };
+        }
+
+        let mut chars = s.chars();
+        if let (Some(ch), None) = (chars.next(), chars.next()) {
+            return Ok(Char(ch));
+        }
+
+        Ok(match s {
+            "esc" => Esc,
+            "ret" => Char('\n'),
+            "tab" => Char('\t'),
+            "space" => Char(' '),
+            "lt" => Char('<'),
+            "gt" => Char('>'),
+            "bs" => Backspace,
+            "del" => Delete,
+            "ins" => Insert,
+            "left" => Left,
+            "right" => Right,
+            "up" => Up,
+            "down" => Down,
+            "home" => Home,
+            "end" => End,
+            "pgup" => PageUp,
+            "pgdn" => PageDown,
+            _ => match s.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n) if (1..=12).contains(&n) => F(n),
+                _ => return Err(err()),
+            },
+        })
+    }
+}
+
 impl NaturalyOrderedKey {
     fn ordering_keys(self) -> (usize, char, usize) {
         use self::Key::*;
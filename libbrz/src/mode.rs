@@ -10,12 +10,18 @@ mod find;
 mod goto;
 mod insert;
 mod normal;
+mod object;
+mod picker;
+mod window;
 
 pub use self::command::Command;
 pub use self::find::Find;
 pub use self::goto::Goto;
 pub use self::insert::Insert;
 pub use self::normal::Normal;
+pub use self::object::Object;
+pub use self::picker::Picker;
+pub use self::window::Window;
 
 pub use crate::render::{self, Coord, Rect, Renderer};
 
@@ -28,6 +34,11 @@ pub trait Mode {
     fn cmd_string(&self) -> Option<String> {
         None
     }
+    /// Does it record its keys into `State::recording_edit`, to finish
+    /// the edit that switched to it
+    fn records_edit(&self) -> bool {
+        false
+    }
 
     fn action_mappings<'s>(&'s self) -> Box<dyn Iterator<Item = action::ActionByKey<'s>> + 's> {
         let actions = self.actions();
@@ -69,6 +80,7 @@ fn default_render_split_status_rect(render: &mut dyn Renderer) -> (Rect, Rect) {
 
 fn default_render_status(
     mode: &(impl Mode + ?Sized),
+    state: &State,
     mut render: &mut dyn Renderer,
     status_rect: Rect,
 ) {
@@ -82,6 +94,21 @@ fn default_render_status(
         mode.name4(),
         style,
     );
+
+    if let Some(buffer) = state.cur_buffer_opt() {
+        let info = format!("{} {}", buffer.filetype.name, buffer.indent_status());
+        status_view.print(
+            render::Coord {
+                x: status_rect
+                    .dimensions
+                    .x
+                    .saturating_sub(5 + info.chars().count()),
+                y: 0,
+            },
+            &info,
+            style,
+        );
+    }
 }
 
 fn default_render(
@@ -95,11 +122,50 @@ fn default_render(
         default_render_available_actions(mode, state, render, buffer_rect);
     }
 
-    default_render_status(mode, render, status_rect);
+    default_render_status(mode, state, render, status_rect);
+    default_render_msg(state, render, buffer_rect, status_rect);
 
     (buffer_rect, status_rect)
 }
 
+/// Render `state.msg`
+///
+/// Single line messages go to the status line, longer ones
+/// are drawn over the bottom of the buffer.
+fn default_render_msg(
+    state: &State,
+    mut render: &mut dyn Renderer,
+    buffer_rect: Rect,
+    status_rect: Rect,
+) {
+    let msg = if let Some(msg) = state.msg.as_ref() {
+        msg
+    } else {
+        return;
+    };
+
+    let style = render.color_map().default;
+    let lines: Vec<_> = msg.lines().collect();
+
+    if lines.len() <= 1 {
+        let mut status_view = status_rect.to_renderer(&mut render);
+        status_view.print(render::Coord { x: 0, y: 0 }, msg, style);
+        return;
+    }
+
+    let height = min(lines.len(), buffer_rect.dimensions.y.saturating_sub(1));
+    if height == 0 {
+        return;
+    }
+
+    let rect = buffer_rect.split_horizontaly_at(-(height as isize)).1;
+    let mut view = rect.to_renderer(&mut render);
+    view.fill(view.dimensions_rect(), ' ', style);
+    for (i, line) in lines.iter().skip(lines.len() - height).enumerate() {
+        view.print(render::Coord { x: 0, y: i }, line, style);
+    }
+}
+
 fn default_render_available_actions(
     mode: &(impl Mode + ?Sized),
     state: &State,
@@ -1,4 +1,6 @@
 use super::*;
+use crate::buffer::SortBy;
+use crate::window::SplitDirection;
 
 #[derive(Clone, Debug, Default)]
 pub struct Command {
@@ -53,7 +55,12 @@ impl Mode for Command {
 
 impl Command {
     fn handle_command_complete(&self, state: &mut State) {
-        let cmd: Vec<_> = self.cmd.split_whitespace().map(str::to_owned).collect();
+        Self::execute(state, &self.cmd);
+    }
+
+    /// Execute a command line (without the leading `:`)
+    pub fn execute(state: &mut State, cmd_line: &str) {
+        let cmd: Vec<_> = cmd_line.split_whitespace().map(str::to_owned).collect();
         if cmd.is_empty() {
             return;
         }
@@ -76,10 +83,87 @@ impl Command {
             "db" => {
                 state.delete_buffer();
             }
+            "ls" => {
+                state.buffer_list();
+            }
+            "b" => {
+                if let Some(name) = cmd.get(1) {
+                    state.buffer_switch(name);
+                } else {
+                    state.buffer_alternate();
+                }
+            }
+            "new" => {
+                state.new_scratch_buffer();
+            }
+            "split" | "sp" => {
+                state.split_window(SplitDirection::Horizontal);
+            }
+            "vsplit" | "vs" => {
+                state.split_window(SplitDirection::Vertical);
+            }
+            "close" => {
+                state.close_window();
+            }
+            "only" => {
+                state.only_window();
+            }
+            "set" => match cmd.get(1) {
+                Some(name) => state.set_option(name, cmd.get(2).map(String::as_str)),
+                None => state.msg = Some("usage: set <option> [value]".into()),
+            },
+            "seq" => {
+                let start = cmd.get(1).map(String::as_str).unwrap_or("1");
+                let step = cmd.get(2).map(String::as_str).unwrap_or("1");
+                match (start.parse(), step.parse()) {
+                    (Ok(start_n), Ok(step)) => {
+                        // `:seq 001` pads with zeros to 3 digits
+                        let digits = start.trim_start_matches('-');
+                        let width = if digits.starts_with('0') {
+                            digits.len()
+                        } else {
+                            0
+                        };
+                        if !state
+                            .cur_buffer_mut()
+                            .insert_number_sequence(start_n, step, width)
+                        {
+                            state.msg = Some("Sequence out of range".into());
+                        }
+                    }
+                    _ => state.msg = Some("usage: seq [start] [step]".into()),
+                }
+            }
+            "sort" => {
+                let sort_by = match cmd.get(1).map(String::as_str) {
+                    None => Some(SortBy::Lexical),
+                    Some("n") => Some(SortBy::Numeric),
+                    Some("i") => Some(SortBy::CaseInsensitive),
+                    Some(_) => None,
+                };
+                match sort_by {
+                    Some(sort_by) => state.cur_buffer_mut().sort_contents(sort_by),
+                    None => state.msg = Some("usage: sort [n|i]".into()),
+                }
+            }
+            "uniq" => {
+                state.cur_buffer_mut().unique_contents();
+            }
+            "reverse" => {
+                state.cur_buffer_mut().reverse_contents();
+            }
+            "format" => {
+                state.format_buffer();
+            }
             "w" => {
                 state.write_buffer(cmd.get(1).map(PathBuf::from));
             }
-            _ => state.msg = Some(format!("unrecognized command: {}", self.cmd)),
+            name => {
+                let args = cmd[1..].join(" ");
+                if !state.run_plugin_command(name, &args) {
+                    state.msg = Some(format!("unrecognized command: {}", cmd_line));
+                }
+            }
         }
     }
 }
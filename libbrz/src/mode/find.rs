@@ -1,85 +1,74 @@
 use super::*;
 
-#[derive(Default, Debug, Clone)]
+use crate::buffer::FindChar;
+
+/// Waiting for the char to search for with `f`, `t` and friends
+#[derive(Clone, Debug)]
 pub struct Find {
-    match_str: String,
-    cur_matches: Vec<PathBuf>,
+    forward: bool,
+    till: bool,
+    extend: bool,
+    times: usize,
 }
 
 impl Find {
-    fn update_matches(&mut self, state: &State) {
-        self.cur_matches = (state.find_handler)(&self.match_str)
-            .ok()
-            .unwrap_or_else(|| vec![]);
+    pub fn new(forward: bool, till: bool, extend: bool, times: usize) -> Self {
+        Self {
+            forward,
+            till,
+            extend,
+            times,
+        }
     }
 }
+
 impl Mode for Find {
     fn name(&self) -> &str {
-        "find"
+        "find char"
     }
 
-    fn on_enter(&mut self, state: &State) {
-        self.update_matches(state);
+    // not `find`, like the file picker
+    fn name4(&self) -> &str {
+        "char"
     }
 
     fn handle(&mut self, state: &mut State, key: Key) {
-        match key {
-            Key::Esc => {
-                self.match_str = "".into();
-                state.set_mode(Normal::default());
-                return;
-            }
-            Key::Backspace => {
-                self.match_str.pop();
-            }
-            Key::Char('\n') => {
-                if let Some(path) = self.cur_matches.get(0) {
-                    state.open_buffer(path);
-                }
-                self.match_str = "".into();
-                state.set_mode(Normal::default());
-            }
-            Key::Char(ch) => {
-                self.match_str.push(ch);
-            }
+        state.set_mode(Normal);
+        let ch = match key {
+            Key::Char(ch) => ch,
             _ => return,
-        }
-        self.update_matches(state)
+        };
+        let find = FindChar {
+            ch,
+            forward: self.forward,
+            till: self.till,
+            extend: self.extend,
+        };
+        state.last_find = Some(find);
+        state.cur_buffer_mut().find_char(find, self.times);
     }
+}
 
-    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
-        let dimensions = render.dimensions();
-        let (buffer_rect, status_rect) = super::default_render_split_status_rect(render);
-        let (buffer_rect, results_rect) =
-            buffer_rect.split_horizontaly_at(-(dimensions.y as isize / 4));
-        state.render_buffer(&mut buffer_rect.to_renderer(&mut render));
-
-        default_render_status(self, render, status_rect);
+#[test]
+fn find_char_test() {
+    let mut brz = crate::headless::headless_with_text("a,b,c\nd,e,f\n");
 
-        let style_default = render.color_map().default;
-        let style_selected = render.color_map().selection;
-        let mut view = results_rect.to_renderer(&mut render);
-        let view_y = view.dimensions().y;
-        for (i, match_) in self.cur_matches.iter().enumerate().take(view_y) {
-            view.print(
-                render::Coord {
-                    x: 0,
-                    y: view_y.saturating_sub(1).saturating_sub(i),
-                },
-                &format!("{}", match_.display()),
-                if i == 0 {
-                    style_selected
-                } else {
-                    style_default
-                },
-            );
-        }
+    brz.keys("f");
+    assert_eq!(brz.state.mode_name(), "find char");
+    assert!(brz.snapshot().contains("char"));
+    brz.keys(",");
+    assert_eq!(brz.selections(), vec![(0, 2)]);
+    brz.keys("2f,");
+    assert_eq!(brz.selections(), vec![(2, 8)]);
+    brz.keys("<a-.>");
+    assert_eq!(brz.selections(), vec![(8, 10)]);
+    brz.keys("f,");
+    assert_eq!(brz.selections(), vec![(8, 10)]);
 
-        let mut status_view = status_rect.to_renderer(&mut render);
-        status_view.print(
-            render::Coord { x: 0, y: 0 },
-            &format!("find: {}", self.match_str),
-            style_default,
-        );
-    }
+    brz.keys("<a-t>b");
+    assert_eq!(brz.selections(), vec![(10, 3)]);
+    brz.keys("Tc");
+    assert_eq!(brz.selections(), vec![(10, 4)]);
+    brz.keys("<a-F>a");
+    assert_eq!(brz.selections(), vec![(10, 0)]);
 }
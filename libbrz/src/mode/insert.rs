@@ -1,17 +1,70 @@
 use super::*;
+use crate::buffer::Buffer;
+use crate::util::char;
 
 #[derive(Clone, Debug, Default)]
 pub struct Insert {
     extend: bool,
+    /// Auto-inserted closers still right after the cursors
+    auto_closed: usize,
 }
 
 impl Insert {
     pub fn new_normal() -> Self {
-        Self { extend: false }
+        Self {
+            extend: false,
+            auto_closed: 0,
+        }
     }
 
     pub fn new_extend() -> Self {
-        Self { extend: true }
+        Self {
+            extend: true,
+            auto_closed: 0,
+        }
+    }
+
+    /// Handle `ch` as a part of a pair, if it is one
+    ///
+    /// Returns `false` if `ch` should be inserted as usual.
+    fn insert_auto_pair(&mut self, buffer: &mut Buffer, ch: char) -> bool {
+        let pairs = buffer.filetype.auto_pairs;
+        let is_closer =
+            char::is_closing_indent(ch) || (pairs.contains(&ch) && !char::is_opening_indent(ch));
+
+        if self.auto_closed > 0 && is_closer && buffer.cursors_before(ch) {
+            buffer.step_over(self.extend);
+            self.auto_closed -= 1;
+            return true;
+        }
+
+        if !pairs.contains(&ch) || char::is_closing_indent(ch) {
+            return false;
+        }
+        let close = matching_char(ch);
+        // don't pair quotes used as apostrophes, or to close a word
+        if ch == close && buffer.any_cursor_after_word() {
+            return false;
+        }
+
+        buffer.insert_pair(ch, close, self.extend);
+        self.auto_closed += 1;
+        true
+    }
+
+    /// Delete an empty pair around the cursors, if there's one
+    fn backspace_auto_pair(&mut self, buffer: &mut Buffer) -> bool {
+        let pairs = buffer.filetype.auto_pairs;
+        if pairs
+            .iter()
+            .any(|&open| buffer.cursors_between(open, matching_char(open)))
+        {
+            buffer.delete_around_cursors();
+            self.auto_closed = self.auto_closed.saturating_sub(1);
+            true
+        } else {
+            false
+        }
     }
 }
 
@@ -19,10 +72,19 @@ impl Mode for Insert {
     fn name(&self) -> &str {
         "insert"
     }
+    fn records_edit(&self) -> bool {
+        true
+    }
     fn handle(&mut self, state: &mut State, key: Key) {
+        if let Some(edit) = state.recording_edit.as_mut() {
+            edit.keys.push(key);
+        }
+
+        let auto_pairs = state.auto_pairs;
         let buffer = state.cur_buffer_mut();
         match key {
             Key::Esc => {
+                state.last_edit = state.recording_edit.take();
                 state.set_mode(Normal::default());
             }
             Key::Char('\n') => {
@@ -32,9 +94,13 @@ impl Mode for Insert {
                 buffer.insert_tab(self.extend);
             }
             Key::Backspace => {
-                buffer.backspace(self.extend);
+                let handled = auto_pairs && self.backspace_auto_pair(buffer);
+                if !handled {
+                    buffer.backspace(self.extend);
+                }
             }
             Key::Left => {
+                self.auto_closed = 0;
                 if self.extend {
                     buffer.extend_cursor_backward(1);
                 } else {
@@ -42,6 +108,7 @@ impl Mode for Insert {
                 }
             }
             Key::Right => {
+                self.auto_closed = 0;
                 if self.extend {
                     buffer.extend_cursor_forward(1);
                 } else {
@@ -49,6 +116,7 @@ impl Mode for Insert {
                 }
             }
             Key::Up => {
+                self.auto_closed = 0;
                 if self.extend {
                     buffer.extend_cursor_up(1);
                 } else {
@@ -56,14 +124,16 @@ impl Mode for Insert {
                 }
             }
             Key::Down => {
+                self.auto_closed = 0;
                 if self.extend {
                     buffer.extend_cursor_down(1);
                 } else {
                     buffer.move_cursor_down(1);
                 }
             }
-            Key::Char(ch) => {
-                if !ch.is_control() {
+            Key::Char(ch) if !ch.is_control() => {
+                let handled = auto_pairs && self.insert_auto_pair(buffer, ch);
+                if !handled {
                     buffer.insert_char(ch, self.extend);
                 }
             }
@@ -71,3 +141,23 @@ impl Mode for Insert {
         }
     }
 }
+
+#[test]
+fn auto_pair_test() {
+    let mut brz = crate::headless::headless_with_text("ab\ncd\n");
+    brz.select(&[(0, 0), (3, 3)]);
+
+    brz.keys("i(x)");
+    assert_eq!(brz.text(), "(x)ab\n(x)cd\n");
+    assert_eq!(brz.selections(), vec![(3, 3), (9, 9)]);
+
+    brz.keys("[<bs>\"<esc>");
+    assert_eq!(brz.text(), "(x)\"\"ab\n(x)\"\"cd\n");
+
+    // typed, not auto-inserted closers are not stepped over
+    brz.keys("i)<esc>");
+    assert_eq!(brz.text(), "(x)\")\"ab\n(x)\")\"cd\n");
+
+    brz.keys(":set autopairs off<ret>i{<esc>");
+    assert_eq!(brz.text(), "(x)\"){\"ab\n(x)\"){\"cd\n");
+}
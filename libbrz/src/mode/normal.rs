@@ -1,7 +1,8 @@
 use super::*;
 
 use crate::action;
-use crate::state::State;
+use crate::buffer::Buffer;
+use crate::state::{Edit, State};
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Normal;
@@ -18,7 +19,13 @@ impl Mode for Normal {
                     state.set_mode(Command::new());
                 }
                 Key::Ctrl('p') => {
-                    state.set_mode(Find::default());
+                    state.set_mode(Picker::find_file());
+                }
+                Key::Ctrl('^') | Key::Ctrl('6') => {
+                    state.buffer_alternate();
+                }
+                Key::Ctrl('w') => {
+                    state.set_mode(Window);
                 }
                 _ => {}
             }
@@ -35,6 +42,10 @@ impl Mode for Normal {
                         .saturating_add(n as usize - '0' as usize),
                 );
             }
+            Key::Char('.') => {
+                state.repeat_last_edit();
+                state.num_prefix = None;
+            }
             other => {
                 state
                     .cur_buffer_state_mut_opt()
@@ -70,8 +81,33 @@ impl Normal {
                 state.cur_buffer_state_mut().redo(times);
                 true
             }
-            other => self.handle_not_digit_not_undo(state, other),
+            other => self.handle_recording_edit(state, other),
+        }
+    }
+
+    /// Handle `key`, and remember it as the last edit, if it was one
+    ///
+    /// If `key` switches to the insert mode, the insert mode keeps
+    /// recording until it's done.
+    fn handle_recording_edit(&self, state: &mut State, key: Key) -> bool {
+        let buffer_i = state.cur_buffer_i;
+        let version = state.cur_buffer_opt().map(Buffer::version);
+        state.recording_edit = Some(Edit {
+            num_prefix: state.num_prefix,
+            keys: vec![key],
+        });
+
+        let res = self.handle_not_digit_not_undo(state, key);
+
+        if !state.entering_insert() {
+            let edit = state.recording_edit.take();
+            if state.cur_buffer_i == buffer_i
+                && state.cur_buffer_opt().map(Buffer::version) != version
+            {
+                state.last_edit = edit;
+            }
         }
+        res
     }
 
     fn handle_not_digit_not_undo(&self, state: &mut State, key: Key) -> bool {
@@ -83,7 +119,14 @@ impl Normal {
                 buffer.collapse();
             }
             Key::Ctrl('p') => {
-                state.set_mode(Find::default());
+                state.set_mode(Picker::find_file());
+            }
+            // terminals report `c-^` as `c-6`
+            Key::Ctrl('^') | Key::Ctrl('6') => {
+                state.buffer_alternate();
+            }
+            Key::Ctrl('w') => {
+                state.set_mode(Window);
             }
             Key::Char('g') => {
                 if let Some(num_prefix) = state.num_prefix {
@@ -160,6 +203,58 @@ impl Normal {
             Key::Char('B') => {
                 state.cur_buffer_mut().extend_cursor_2(Idx::backward_word);
             }
+            Key::Char('f') => state.set_mode(Find::new(true, false, false, times)),
+            Key::Char('t') => state.set_mode(Find::new(true, true, false, times)),
+            Key::Char('F') => state.set_mode(Find::new(true, false, true, times)),
+            Key::Char('T') => state.set_mode(Find::new(true, true, true, times)),
+            Key::Alt('f') => state.set_mode(Find::new(false, false, false, times)),
+            Key::Alt('t') => state.set_mode(Find::new(false, true, false, times)),
+            Key::Alt('F') => state.set_mode(Find::new(false, false, true, times)),
+            Key::Alt('T') => state.set_mode(Find::new(false, true, true, times)),
+            Key::Alt('.') => {
+                if let Some(find) = state.last_find {
+                    state.cur_buffer_mut().find_char(find, times);
+                }
+            }
+            Key::Char('m') => {
+                state.cur_buffer_mut().select_matching_bracket(false);
+            }
+            Key::Char('M') => {
+                state.cur_buffer_mut().select_matching_bracket(true);
+            }
+            Key::Char('`') => {
+                state.cur_buffer_mut().to_lowercase();
+            }
+            Key::Char('~') => {
+                state.cur_buffer_mut().to_uppercase();
+            }
+            Key::Alt('`') => {
+                state.cur_buffer_mut().swap_case();
+            }
+            Key::Alt('~') => {
+                state.cur_buffer_mut().cycle_identifier_case();
+            }
+            Key::Ctrl('a') => {
+                state.cur_buffer_mut().increment_numbers(times as i64);
+            }
+            Key::Ctrl('x') => {
+                state.cur_buffer_mut().increment_numbers(-(times as i64));
+            }
+            Key::Alt('j') => {
+                state.cur_buffer_mut().join_lines();
+            }
+            Key::Alt(')') => {
+                state.cur_buffer_mut().rotate_contents(times as isize);
+            }
+            Key::Alt('(') => {
+                state.cur_buffer_mut().rotate_contents(-(times as isize));
+            }
+            Key::Char('&') => {
+                state.cur_buffer_mut().align_selections();
+            }
+            Key::Alt('&') => {
+                state.cur_buffer_mut().copy_indent();
+            }
             Key::Char('x') => {
                 state.cur_buffer_mut().move_line();
             }
@@ -172,11 +267,115 @@ impl Normal {
             Key::Char('\'') | Key::Alt(';') => {
                 state.cur_buffer_mut().reverse_selections();
             }
-            key => self
-                .action_by_key(key)
-                .unwrap_or_else(|| &action::ActionNotFound)
-                .execute(state),
+            key => {
+                if let Some(action) = self.action_by_key(key) {
+                    action.execute(state);
+                } else if let Some(name) = state.plugins.action_for_key(key).map(str::to_owned) {
+                    state.run_plugin_action(&name);
+                } else {
+                    action::Action::execute(&action::ActionNotFound, state);
+                }
+            }
         }
         true
     }
 }
+
+#[test]
+fn repeat_edit_test() {
+    let mut brz = crate::headless::headless_with_text("1\n2\n3\n4\n5\n");
+
+    brz.keys("xdx.");
+    assert_eq!(brz.text(), "3\n4\n5\n");
+
+    // repeated with the original `num_prefix`, unless given a new one
+    brz.keys("2>j<space>.j<space>1.");
+    assert_eq!(brz.text(), "        3\n        4\n    5\n");
+
+    brz.keys("ia<ret>b<esc>.");
+    assert_eq!(brz.text(), "        3\n        4\n    5a\nba\nb\n");
+
+    // moves and undo are not edits, so `.` still repeats the insert
+    brz.keys("gku.");
+    assert_eq!(brz.text(), "        3\n        4\n    5a\nba\nb\n");
+}
+
+#[test]
+fn repeat_edit_multiple_selections_test() {
+    let mut brz = crate::headless::headless_with_text("ab\ncd\n");
+    brz.select(&[(0, 0), (3, 3)]);
+
+    brz.keys("i-<esc>.");
+    assert_eq!(brz.text(), "--ab\n--cd\n");
+    assert_eq!(brz.selections().len(), 2);
+}
+
+#[test]
+fn toggle_comment_undo_test() {
+    let mut brz = crate::headless::headless_with_text("a\nb\n");
+    brz.state.cur_buffer_mut().filetype =
+        crate::filetype::from_path(Some(std::path::Path::new("a.rs")));
+
+    brz.keys("%<c-c>");
+    assert_eq!(brz.text(), "// a\n// b\n");
+    brz.keys("<c-c>");
+    assert_eq!(brz.text(), "a\nb\n");
+    brz.keys("u");
+    assert_eq!(brz.text(), "// a\n// b\n");
+    brz.keys("u");
+    assert_eq!(brz.text(), "a\nb\n");
+}
+
+#[test]
+fn align_test() {
+    let mut brz = crate::headless::headless_with_text("a = 1\nbcd = 2\n");
+    brz.select(&[(1, 1), (9, 9)]);
+
+    brz.keys("&");
+    assert_eq!(brz.text(), "a   = 1\nbcd = 2\n");
+    assert_eq!(brz.selections(), vec![(3, 3), (11, 11)]);
+
+    let mut brz = crate::headless::headless_with_text("    a\nb\n\nc\n");
+    brz.select(&[(4, 4), (7, 10)]);
+
+    brz.keys("<a-&>");
+    assert_eq!(brz.text(), "    a\n    b\n\n    c\n");
+}
+
+#[test]
+fn increment_test() {
+    let mut brz = crate::headless::headless_with_text("x = 9;\ny = -1, 0x0f\n");
+    brz.select(&[(0, 0), (7, 7), (15, 15)]);
+
+    brz.keys("<c-a>");
+    assert_eq!(brz.text(), "x = 10;\ny = 0, 0x10\n");
+    assert_eq!(brz.selections(), vec![(4, 6), (12, 13), (15, 19)]);
+    brz.keys("3<c-x>");
+    assert_eq!(brz.text(), "x = 7;\ny = -3, 0x0d\n");
+
+    brz.keys(":seq 08 -2<ret>");
+    assert_eq!(brz.text(), "x = 08;\ny = 06, 04\n");
+
+    brz.keys(":seq 9223372036854775806<ret>");
+    assert_eq!(brz.state.msg.as_deref(), Some("Sequence out of range"));
+    assert_eq!(brz.text(), "x = 08;\ny = 06, 04\n");
+    brz.keys(":seq -9223372036854775808 0<ret>");
+    assert_eq!(
+        brz.text(),
+        "x = -9223372036854775808;\ny = -9223372036854775808, -9223372036854775808\n"
+    );
+}
+
+#[test]
+fn join_lines_test() {
+    let mut brz = crate::headless::headless_with_text("a\n    b\n\nc\nd\n");
+
+    brz.keys("<a-j>");
+    assert_eq!(brz.text(), "a b\n\nc\nd\n");
+    brz.keys("<a-j>");
+    assert_eq!(brz.text(), "a b\nc\nd\n");
+    brz.keys("%<a-j>");
+    assert_eq!(brz.text(), "a b c d\n");
+    brz.keys("u");
+    assert_eq!(brz.text(), "a b\nc\nd\n");
+}
@@ -0,0 +1,53 @@
+use super::*;
+
+use crate::text_object::TextObject;
+
+/// Select a text object, with the next key picking which one
+#[derive(Clone, Debug, Default)]
+pub struct Object {
+    around: bool,
+}
+
+impl Object {
+    pub fn inner() -> Self {
+        Self { around: false }
+    }
+
+    pub fn around() -> Self {
+        Self { around: true }
+    }
+}
+
+impl Mode for Object {
+    fn name(&self) -> &str {
+        "object"
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        state.set_mode(Normal);
+        match key {
+            Key::Esc => {}
+            Key::Char(ch) => {
+                if let Some(object) = TextObject::from_key_char(ch) {
+                    state.cur_buffer_mut().select_object(object, self.around);
+                } else {
+                    state.msg = Some(format!("Unknown object: {}", ch));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn select_object_test() {
+    let mut brz = crate::headless::headless_with_text("f(a, b)\ng(c)\n");
+    brz.select(&[(3, 3), (11, 11)]);
+
+    brz.keys("<a-i>b");
+    assert_eq!(brz.selections(), vec![(2, 6), (10, 11)]);
+
+    brz.keys("<a-a>(");
+    assert_eq!(brz.selections(), vec![(1, 7), (9, 12)]);
+    assert_eq!(brz.state.mode_name(), "normal");
+}
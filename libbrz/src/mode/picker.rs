@@ -0,0 +1,447 @@
+use super::*;
+use crate::action::normal;
+use crate::fuzzy;
+use crate::symbol;
+use std::cmp::Reverse;
+
+#[derive(Debug, Clone)]
+struct PickerMatch {
+    candidate_i: usize,
+    score: i64,
+    positions: Vec<usize>,
+}
+
+/// Items to pick from, after the first `known` ones; see `Picker`
+type PickerItems<T> = dyn Fn(&State, usize) -> Vec<(String, T)>;
+/// Called with the picked item
+type PickerAccept<T> = dyn Fn(&mut State, T);
+
+/// Fuzzy pick one item out of a list
+///
+/// `items` returns the items to pick from, each with a label to match
+/// against and display, skipping the first `known` ones it returned
+/// already. It is called again on every key press, and the list is allowed
+/// to grow between calls (eg. while the directory tree is being walked in
+/// the background), but only by appending to it.
+///
+/// `accept` is called with the picked item, after switching back to the
+/// `Normal` mode.
+pub struct Picker<T> {
+    name: &'static str,
+    items: Box<PickerItems<T>>,
+    accept: Box<PickerAccept<T>>,
+
+    match_str: String,
+    candidates: Vec<(String, T)>,
+    /// `match_str` that `cur_matches` were computed for
+    cur_matches_str: String,
+    /// Sorted from the best match
+    cur_matches: Vec<PickerMatch>,
+    selected: usize,
+}
+
+impl<T> Picker<T>
+where
+    T: Clone + 'static,
+{
+    pub fn new(
+        name: &'static str,
+        items: impl Fn(&State, usize) -> Vec<(String, T)> + 'static,
+        accept: impl Fn(&mut State, T) + 'static,
+    ) -> Self {
+        Self {
+            name,
+            items: Box::new(items),
+            accept: Box::new(accept),
+            match_str: String::new(),
+            candidates: vec![],
+            cur_matches_str: String::new(),
+            cur_matches: vec![],
+            selected: 0,
+        }
+    }
+
+    fn score_candidate(&self, candidate_i: usize, pattern: &str) -> Option<PickerMatch> {
+        fuzzy::score(pattern, &self.candidates[candidate_i].0).map(|m| PickerMatch {
+            candidate_i,
+            score: m.score,
+            positions: m.positions,
+        })
+    }
+
+    /// Fetch any new candidates and match them against `cur_matches_str`
+    fn update_candidates(&mut self, state: &State) {
+        let new_start = self.candidates.len();
+        self.candidates.extend((self.items)(state, new_start));
+
+        for candidate_i in new_start..self.candidates.len() {
+            if let Some(m) = self.score_candidate(candidate_i, &self.cur_matches_str) {
+                self.cur_matches.push(m);
+            }
+        }
+    }
+
+    fn update_matches(&mut self, state: &State) {
+        let known_matches = self.cur_matches.len();
+        self.update_candidates(state);
+        let mut changed = self.cur_matches.len() != known_matches;
+
+        if self.match_str != self.cur_matches_str {
+            changed = true;
+            self.cur_matches = if self.match_str.starts_with(&self.cur_matches_str) {
+                // pattern only got longer, so anything that did not match
+                // before, will not match now either
+                self.cur_matches
+                    .iter()
+                    .filter_map(|m| self.score_candidate(m.candidate_i, &self.match_str))
+                    .collect()
+            } else {
+                (0..self.candidates.len())
+                    .filter_map(|candidate_i| self.score_candidate(candidate_i, &self.match_str))
+                    .collect()
+            };
+            self.cur_matches_str = self.match_str.clone();
+            self.selected = 0;
+        }
+
+        let candidates = &self.candidates;
+        let sort_key = |m: &PickerMatch| {
+            let label = candidates[m.candidate_i].0.as_str();
+            (Reverse(m.score), label.len(), label)
+        };
+        // with an empty pattern all the scores are equal, and the stable sort
+        // keeps the order of the items as given
+        if changed && !self.cur_matches_str.is_empty() {
+            self.cur_matches
+                .sort_by(|a, b| sort_key(a).cmp(&sort_key(b)));
+        }
+
+        self.selected = min(self.selected, self.cur_matches.len().saturating_sub(1));
+    }
+
+    fn select_next(&mut self) {
+        self.selected = min(
+            self.selected.saturating_add(1),
+            self.cur_matches.len().saturating_sub(1),
+        );
+    }
+
+    fn select_prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+}
+
+impl Picker<PathBuf> {
+    /// Pick a file to open out of the ones returned by `find_handler`
+    pub fn find_file() -> Self {
+        Self::new(
+            "find",
+            |state, known| {
+                (state.find_handler)(known)
+                    .ok()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|path| (path.display().to_string(), path))
+                    .collect()
+            },
+            |state, path| state.open_buffer(&path),
+        )
+    }
+
+    /// Pick one of the recently opened files
+    pub fn recent_file() -> Self {
+        Self::new(
+            "recent",
+            |state, known| {
+                state
+                    .recent_files
+                    .iter()
+                    .skip(known)
+                    .map(|path| (path.display().to_string(), path.clone()))
+                    .collect()
+            },
+            |state, path| state.open_buffer(&path),
+        )
+    }
+}
+
+impl Picker<usize> {
+    /// Pick one of the open buffers
+    pub fn buffer() -> Self {
+        Self::new(
+            "buffer",
+            |state, known| {
+                state
+                    .buffers
+                    .iter()
+                    .skip(known)
+                    .map(|(i, buffer_state)| (buffer_state.display_name(), i))
+                    .collect()
+            },
+            |state, i| state.set_cur_buffer(i),
+        )
+    }
+}
+
+impl Picker<&'static str> {
+    /// Pick an action to execute
+    pub fn action() -> Self {
+        Self::new(
+            "action",
+            |_state, known| {
+                normal::actions()
+                    .iter()
+                    .skip(known)
+                    .map(|(id, action)| (format!("{} ({})", action.help(), id), *id))
+                    .collect()
+            },
+            |state, id| {
+                if let Some(action) = normal::actions().get(id) {
+                    action.execute(state);
+                }
+            },
+        )
+    }
+}
+
+impl Picker<Idx> {
+    /// Pick a symbol in the current buffer to jump to
+    pub fn symbol() -> Self {
+        // the text can't change while picking, so they all come with
+        // the first call, in `on_enter`
+        let fetched = std::cell::Cell::new(false);
+        Self::new(
+            "symbol",
+            move |state, _known| {
+                if fetched.replace(true) {
+                    return vec![];
+                }
+                state
+                    .cur_buffer_opt()
+                    .map(|buffer| {
+                        symbol::find_symbols(&buffer.text)
+                            .into_iter()
+                            .map(|(pos, label)| (label, pos.to_idx(&buffer.text)))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            },
+            |state, idx| state.cur_buffer_mut().jump_to(idx),
+        )
+    }
+}
+
+impl<T> Mode for Picker<T>
+where
+    T: Clone + 'static,
+{
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn on_enter(&mut self, state: &State) {
+        self.update_matches(state);
+    }
+
+    fn handle(&mut self, state: &mut State, key: Key) {
+        match key {
+            Key::Esc => {
+                self.match_str = "".into();
+                state.set_mode(Normal);
+                return;
+            }
+            Key::Backspace => {
+                self.match_str.pop();
+            }
+            // matches are displayed from the bottom, so "up" is the next, worse match
+            Key::Up | Key::Ctrl('p') => {
+                self.select_next();
+            }
+            Key::Down | Key::Ctrl('n') => {
+                self.select_prev();
+            }
+            Key::Char('\n') => {
+                let picked = self
+                    .cur_matches
+                    .get(self.selected)
+                    .map(|m| self.candidates[m.candidate_i].1.clone());
+                self.match_str = "".into();
+                state.set_mode(Normal);
+                if let Some(picked) = picked {
+                    (self.accept)(state, picked);
+                }
+                return;
+            }
+            Key::Char(ch) => {
+                self.match_str.push(ch);
+            }
+            _ => return,
+        }
+        self.update_matches(state)
+    }
+
+    fn render(&self, state: &State, mut render: &mut dyn Renderer) {
+        let dimensions = render.dimensions();
+        let (buffer_rect, status_rect) = super::default_render_split_status_rect(render);
+        let (buffer_rect, results_rect) =
+            buffer_rect.split_horizontaly_at(-(dimensions.y as isize / 4));
+        state.render_buffer(&mut buffer_rect.to_renderer(&mut render));
+
+        default_render_status(self, state, render, status_rect);
+
+        let style_default = render.color_map().default;
+        let style_selected = render.color_map().selection;
+        let style_fuzzy_match = render.color_map().fuzzy_match;
+        let mut view = results_rect.to_renderer(&mut render);
+        let view_y = view.dimensions().y;
+        let view_x = view.dimensions().x;
+
+        // scroll so that the selected match is always visible
+        let first_visible = self.selected.saturating_sub(view_y.saturating_sub(1));
+        for (i, m) in self
+            .cur_matches
+            .iter()
+            .enumerate()
+            .skip(first_visible)
+            .take(view_y)
+        {
+            let y = view_y.saturating_sub(1).saturating_sub(i - first_visible);
+            let style = if i == self.selected {
+                style_selected
+            } else {
+                style_default
+            };
+
+            if i == self.selected {
+                view.fill(
+                    Rect {
+                        offset: Coord { x: 0, y },
+                        dimensions: Coord { x: view_x, y: 1 },
+                    },
+                    ' ',
+                    style,
+                );
+            }
+
+            let mut positions = m.positions.iter().peekable();
+            for (x, ch) in self.candidates[m.candidate_i].0.chars().enumerate() {
+                let style = if positions.peek() == Some(&&x) {
+                    positions.next();
+                    style.paintover(style_fuzzy_match)
+                } else {
+                    style
+                };
+                view.put(Coord { x, y }, ch, style);
+            }
+        }
+
+        let mut status_view = status_rect.to_renderer(&mut render);
+        status_view.print(
+            render::Coord { x: 0, y: 0 },
+            &format!(
+                "{}: {} ({}/{})",
+                self.name,
+                self.match_str,
+                min(self.selected + 1, self.cur_matches.len()),
+                self.cur_matches.len()
+            ),
+            style_default,
+        );
+    }
+}
+
+/// Picker of the strings in `items`, that records the accepted one
+#[cfg(test)]
+fn test_picker(
+    items: &std::rc::Rc<std::cell::RefCell<Vec<&'static str>>>,
+    accepted: &std::rc::Rc<std::cell::RefCell<Option<String>>>,
+) -> Picker<String> {
+    let (items, accepted) = (items.clone(), accepted.clone());
+    Picker::new(
+        "test",
+        move |_state, known| {
+            items.borrow()[known..]
+                .iter()
+                .map(|item| (item.to_string(), item.to_string()))
+                .collect()
+        },
+        move |_state, item| *accepted.borrow_mut() = Some(item),
+    )
+}
+
+#[cfg(test)]
+impl<T: Clone + 'static> Picker<T> {
+    fn labels(&self) -> Vec<&str> {
+        self.cur_matches
+            .iter()
+            .map(|m| self.candidates[m.candidate_i].0.as_str())
+            .collect()
+    }
+
+    fn type_keys(&mut self, state: &mut State, keys: &str) {
+        for key in crate::headless::parse_keys(keys).expect("keys") {
+            self.handle(state, key);
+        }
+    }
+}
+
+#[test]
+fn picker_test() {
+    let mut state = State::default();
+    let items = std::rc::Rc::new(std::cell::RefCell::new(vec!["src/main.rs", "README.md"]));
+    let accepted = std::rc::Rc::new(std::cell::RefCell::new(None));
+    let mut picker = test_picker(&items, &accepted);
+
+    picker.on_enter(&state);
+    assert_eq!(picker.labels(), vec!["src/main.rs", "README.md"]);
+
+    // found while picking already
+    items.borrow_mut().push("src/lib.rs");
+    picker.type_keys(&mut state, "s");
+    assert_eq!(picker.labels(), vec!["src/lib.rs", "src/main.rs"]);
+
+    // narrowing, and widening back
+    picker.type_keys(&mut state, "l");
+    assert_eq!(picker.labels(), vec!["src/lib.rs"]);
+    picker.type_keys(&mut state, "<bs>");
+    assert_eq!(picker.labels(), vec!["src/lib.rs", "src/main.rs"]);
+    picker.type_keys(&mut state, "<bs>");
+    assert_eq!(
+        picker.labels(),
+        vec!["src/main.rs", "README.md", "src/lib.rs"]
+    );
+
+    // the selection stays within the matches
+    picker.type_keys(&mut state, "<up><up><up><up>");
+    assert_eq!(picker.selected, 2);
+    picker.type_keys(&mut state, "<down><down><down><down>");
+    assert_eq!(picker.selected, 0);
+    picker.type_keys(&mut state, "<up><up>s");
+    assert_eq!(picker.selected, 0);
+    picker.type_keys(&mut state, "<up>zzz");
+    assert_eq!(picker.labels(), Vec::<&str>::new());
+    assert_eq!(picker.selected, 0);
+
+    // nothing to accept
+    picker.type_keys(&mut state, "<ret>");
+    assert_eq!(*accepted.borrow(), None);
+    assert_eq!(state.mode_name(), "normal");
+
+    picker.on_enter(&state);
+    picker.type_keys(&mut state, "s<up><ret>");
+    assert_eq!(accepted.borrow().as_deref(), Some("src/main.rs"));
+    assert_eq!(picker.match_str, "");
+}
+
+#[test]
+fn symbol_picker_test() {
+    let mut brz = crate::headless::headless_with_text("fn foo() {\n}\n\nfn bar() {\n}\n");
+    let mut picker = Picker::symbol();
+
+    picker.on_enter(&brz.state);
+    picker.type_keys(&mut brz.state, "fn<bs><bs>");
+    assert_eq!(picker.labels(), vec!["fn foo()", "fn bar()"]);
+
+    picker.type_keys(&mut brz.state, "bar<ret>");
+    assert_eq!(brz.selections(), vec![(14, 14)]);
+}
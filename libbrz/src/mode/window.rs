@@ -0,0 +1,60 @@
+use super::*;
+use crate::window::{Direction, SplitDirection};
+
+/// Percent by which a window is resized
+const RESIZE_STEP: isize = 5;
+
+#[derive(Clone, Debug, Default)]
+pub struct Window;
+
+impl Mode for Window {
+    fn name(&self) -> &str {
+        "window"
+    }
+    fn handle(&mut self, state: &mut State, key: Key) {
+        state.set_mode(Normal);
+        match key {
+            Key::Esc => {}
+            Key::Char('h') | Key::Left => {
+                state.focus_window_in_direction(Direction::Left);
+            }
+            Key::Char('j') | Key::Down => {
+                state.focus_window_in_direction(Direction::Down);
+            }
+            Key::Char('k') | Key::Up => {
+                state.focus_window_in_direction(Direction::Up);
+            }
+            Key::Char('l') | Key::Right => {
+                state.focus_window_in_direction(Direction::Right);
+            }
+            Key::Char('w') | Key::Ctrl('w') => {
+                state.focus_window_next();
+            }
+            Key::Char('s') => {
+                state.split_window(SplitDirection::Horizontal);
+            }
+            Key::Char('v') => {
+                state.split_window(SplitDirection::Vertical);
+            }
+            Key::Char('c') | Key::Char('q') => {
+                state.close_window();
+            }
+            Key::Char('o') => {
+                state.only_window();
+            }
+            Key::Char('+') => {
+                state.resize_window(SplitDirection::Horizontal, RESIZE_STEP);
+            }
+            Key::Char('-') => {
+                state.resize_window(SplitDirection::Horizontal, -RESIZE_STEP);
+            }
+            Key::Char('>') => {
+                state.resize_window(SplitDirection::Vertical, RESIZE_STEP);
+            }
+            Key::Char('<') => {
+                state.resize_window(SplitDirection::Vertical, -RESIZE_STEP);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,665 @@
+//! Sandboxed WebAssembly plugins
+//!
+//! Plugins can only see and do what they were given `Capability`-ies for:
+//! host functions for missing capabilities are not linked at all, so a plugin
+//! importing them fails to load. All the host functions live in the `brz`
+//! import module:
+//!
+//! * `message(ptr, len)` - display a message
+//! * `buffer_len() -> i32` (`ReadBuffer`) - chars in the current buffer
+//! * `buffer_read(start, end, ptr, cap) -> i32` (`ReadBuffer`) - copy
+//!   `start..end` chars as UTF-8 into `ptr`; returns the length of the whole
+//!   text, which might be more than `cap`
+//! * `selection_count() -> i32`, `selection_anchor(i) -> i32`,
+//!   `selection_cursor(i) -> i32` (`ReadBuffer`)
+//! * `buffer_replace(start, end, ptr, len) -> i32` (`EditBuffer`)
+//! * `buffer_insert(ptr, len) -> i32` (`EditBuffer`) - insert at every cursor
+//! * `register_action(name_ptr, name_len, help_ptr, help_len) -> i32`
+//!   (`Actions`) - the returned id is passed to the `on_action(id)` export
+//! * `map_key(key_ptr, key_len, name_ptr, name_len) -> i32` (`Actions`) - map
+//!   a key (`x`, `a-x`, `c-x`) in the normal mode to one of the plugin actions
+//! * `register_command(name_ptr, name_len) -> i32` (`Commands`) - the returned
+//!   id is passed to the `on_command(id)` export
+//! * `command_args(ptr, cap) -> i32` (`Commands`) - like `buffer_read`, for
+//!   the arguments of the command being executed
+//!
+//! Functions returning `i32` return `-1` on failure. Plugin can optionally
+//! export `init()`, called after loading. Every call into a plugin gets
+//! a fixed amount of fuel, and plugin memory can't grow past the limit,
+//! so a misbehaving plugin can't hang the editor.
+//!
+//! Actions and commands are registered as `<plugin>.<name>`.
+
+use crate::buffer::Buffer;
+use crate::prelude::*;
+use crate::{Idx, Key};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use wasmi::{
+    Caller, Config, Engine, Extern, Instance, Linker, Module, Store, StoreLimits,
+    StoreLimitsBuilder,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capability {
+    ReadBuffer,
+    EditBuffer,
+    Actions,
+    Commands,
+}
+
+impl Capability {
+    pub fn all() -> &'static [Capability] {
+        &[
+            Capability::ReadBuffer,
+            Capability::EditBuffer,
+            Capability::Actions,
+            Capability::Commands,
+        ]
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Limits {
+    /// Fuel for every single call into a plugin
+    pub fuel: u64,
+    /// Maximum size of the plugin memory in bytes
+    pub memory: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            fuel: 10_000_000,
+            memory: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// Things done by a plugin during a call, applied once it returns
+enum Registration {
+    Action { name: String, help: String },
+    KeyMapping { key: Key, action: String },
+    Command { name: String },
+}
+
+/// Store data, that host functions work with
+struct HostData {
+    limits: StoreLimits,
+    /// Copy of the current buffer, for the duration of a call
+    buffer: Option<Buffer>,
+    buffer_modified: bool,
+    command_args: String,
+    registrations: Vec<Registration>,
+    msg: Option<String>,
+    next_action_id: i32,
+    next_command_id: i32,
+}
+
+/// Result of calling into a plugin
+pub(crate) struct CallOutput {
+    /// Current buffer, if it was modified
+    pub buffer: Option<Buffer>,
+    pub msg: Option<String>,
+}
+
+struct Plugin {
+    name: String,
+    store: Store<HostData>,
+    instance: Instance,
+    limits: Limits,
+    fuel_added: u64,
+    /// Action and command names, by the ids given out to the plugin
+    actions: Vec<String>,
+    commands: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PluginAction {
+    plugin_i: usize,
+    id: i32,
+    pub help: String,
+}
+
+/// All the loaded plugins and what they registered
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+    actions: BTreeMap<String, PluginAction>,
+    commands: BTreeMap<String, (usize, i32)>,
+    key_mappings: BTreeMap<Key, String>,
+}
+
+fn read_str(caller: &Caller<'_, HostData>, ptr: i32, len: i32) -> Option<String> {
+    let memory = caller.get_export("memory").and_then(Extern::into_memory)?;
+    let ptr = usize::try_from(ptr).ok()?;
+    let len = usize::try_from(len).ok()?;
+    // borrowed in place, so a bogus `len` can't make us allocate it
+    let bytes = memory.data(caller).get(ptr..ptr.checked_add(len)?)?;
+    std::str::from_utf8(bytes).ok().map(str::to_owned)
+}
+
+/// Copy as much of `s` as fits into the plugin memory; returns the full length
+fn write_str(caller: &mut Caller<'_, HostData>, s: &str, ptr: i32, cap: i32) -> i32 {
+    let memory = match caller.get_export("memory").and_then(Extern::into_memory) {
+        Some(memory) => memory,
+        None => return -1,
+    };
+    let (ptr, cap) = match (usize::try_from(ptr), usize::try_from(cap)) {
+        (Ok(ptr), Ok(cap)) => (ptr, cap),
+        _ => return -1,
+    };
+    let bytes = s.as_bytes();
+    let len = std::cmp::min(cap, bytes.len());
+    if memory.write(caller, ptr, &bytes[..len]).is_err() {
+        return -1;
+    }
+    bytes.len() as i32
+}
+
+/// `start..end` char range, if valid in `buffer`
+fn char_range(buffer: &Buffer, start: i32, end: i32) -> Option<std::ops::Range<Idx>> {
+    let start = usize::try_from(start).ok()?;
+    let end = usize::try_from(end).ok()?;
+    if start <= end && end <= buffer.text.len_chars() {
+        Some(Idx(start)..Idx(end))
+    } else {
+        None
+    }
+}
+
+fn link(linker: &mut Linker<HostData>, capabilities: &[Capability]) -> Result<()> {
+    linker
+        .func_wrap(
+            "brz",
+            "message",
+            |mut caller: Caller<'_, HostData>, ptr: i32, len: i32| {
+                let msg = read_str(&caller, ptr, len);
+                caller.data_mut().msg = msg;
+            },
+        )
+        .map_err(|e| failure::format_err!("{}", e))?;
+
+    for capability in capabilities {
+        match capability {
+            Capability::ReadBuffer => link_read_buffer(linker),
+            Capability::EditBuffer => link_edit_buffer(linker),
+            Capability::Actions => link_actions(linker),
+            Capability::Commands => link_commands(linker),
+        }
+        .map_err(|e| failure::format_err!("{}", e))?;
+    }
+    Ok(())
+}
+
+fn link_read_buffer(
+    linker: &mut Linker<HostData>,
+) -> std::result::Result<(), wasmi::errors::LinkerError> {
+    linker.func_wrap("brz", "buffer_len", |caller: Caller<'_, HostData>| {
+        caller
+            .data()
+            .buffer
+            .as_ref()
+            .map(|b| b.text.len_chars() as i32)
+            .unwrap_or(-1)
+    })?;
+    linker.func_wrap(
+        "brz",
+        "buffer_read",
+        |mut caller: Caller<'_, HostData>, start: i32, end: i32, ptr: i32, cap: i32| {
+            let text = match caller.data().buffer.as_ref() {
+                Some(buffer) => match char_range(buffer, start, end) {
+                    Some(range) => buffer.text.slice(range.start.0..range.end.0).to_string(),
+                    None => return -1,
+                },
+                None => return -1,
+            };
+            write_str(&mut caller, &text, ptr, cap)
+        },
+    )?;
+    linker.func_wrap("brz", "selection_count", |caller: Caller<'_, HostData>| {
+        caller
+            .data()
+            .buffer
+            .as_ref()
+            .map(|b| b.selection.selections.len() as i32)
+            .unwrap_or(-1)
+    })?;
+    linker.func_wrap(
+        "brz",
+        "selection_anchor",
+        |caller: Caller<'_, HostData>, i: i32| {
+            caller
+                .data()
+                .buffer
+                .as_ref()
+                .and_then(|b| b.selection.selections.get(usize::try_from(i).ok()?))
+                .map(|sel| sel.anchor.0 as i32)
+                .unwrap_or(-1)
+        },
+    )?;
+    linker.func_wrap(
+        "brz",
+        "selection_cursor",
+        |caller: Caller<'_, HostData>, i: i32| {
+            caller
+                .data()
+                .buffer
+                .as_ref()
+                .and_then(|b| b.selection.selections.get(usize::try_from(i).ok()?))
+                .map(|sel| sel.cursor.0 as i32)
+                .unwrap_or(-1)
+        },
+    )?;
+    Ok(())
+}
+
+fn link_edit_buffer(
+    linker: &mut Linker<HostData>,
+) -> std::result::Result<(), wasmi::errors::LinkerError> {
+    linker.func_wrap(
+        "brz",
+        "buffer_replace",
+        |mut caller: Caller<'_, HostData>, start: i32, end: i32, ptr: i32, len: i32| {
+            let s = match read_str(&caller, ptr, len) {
+                Some(s) => s,
+                None => return -1,
+            };
+            let data = caller.data_mut();
+            match data.buffer.as_mut() {
+                Some(buffer) => match char_range(buffer, start, end) {
+                    Some(range) => {
+                        buffer.replace_range(range, &s);
+                        data.buffer_modified = true;
+                        0
+                    }
+                    None => -1,
+                },
+                None => -1,
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "brz",
+        "buffer_insert",
+        |mut caller: Caller<'_, HostData>, ptr: i32, len: i32| {
+            let s = match read_str(&caller, ptr, len) {
+                Some(s) => s,
+                None => return -1,
+            };
+            let data = caller.data_mut();
+            match data.buffer.as_mut() {
+                Some(buffer) => {
+                    buffer.insert(&s, false);
+                    data.buffer_modified = true;
+                    0
+                }
+                None => -1,
+            }
+        },
+    )?;
+    Ok(())
+}
+
+fn link_actions(
+    linker: &mut Linker<HostData>,
+) -> std::result::Result<(), wasmi::errors::LinkerError> {
+    linker.func_wrap(
+        "brz",
+        "register_action",
+        |mut caller: Caller<'_, HostData>,
+         name_ptr: i32,
+         name_len: i32,
+         help_ptr: i32,
+         help_len: i32| {
+            let (name, help) = match (
+                read_str(&caller, name_ptr, name_len),
+                read_str(&caller, help_ptr, help_len),
+            ) {
+                (Some(name), Some(help)) => (name, help),
+                _ => return -1,
+            };
+            let data = caller.data_mut();
+            data.registrations.push(Registration::Action { name, help });
+            data.next_action_id += 1;
+            data.next_action_id - 1
+        },
+    )?;
+    linker.func_wrap(
+        "brz",
+        "map_key",
+        |mut caller: Caller<'_, HostData>,
+         key_ptr: i32,
+         key_len: i32,
+         name_ptr: i32,
+         name_len: i32| {
+            let (key, action) = match (
+                read_str(&caller, key_ptr, key_len).and_then(|key| key.parse().ok()),
+                read_str(&caller, name_ptr, name_len),
+            ) {
+                (Some(key), Some(action)) => (key, action),
+                _ => return -1,
+            };
+            caller
+                .data_mut()
+                .registrations
+                .push(Registration::KeyMapping { key, action });
+            0
+        },
+    )?;
+    Ok(())
+}
+
+fn link_commands(
+    linker: &mut Linker<HostData>,
+) -> std::result::Result<(), wasmi::errors::LinkerError> {
+    linker.func_wrap(
+        "brz",
+        "register_command",
+        |mut caller: Caller<'_, HostData>, name_ptr: i32, name_len: i32| {
+            let name = match read_str(&caller, name_ptr, name_len) {
+                Some(name) => name,
+                None => return -1,
+            };
+            let data = caller.data_mut();
+            data.registrations.push(Registration::Command { name });
+            data.next_command_id += 1;
+            data.next_command_id - 1
+        },
+    )?;
+    linker.func_wrap(
+        "brz",
+        "command_args",
+        |mut caller: Caller<'_, HostData>, ptr: i32, cap: i32| {
+            let args = caller.data().command_args.clone();
+            write_str(&mut caller, &args, ptr, cap)
+        },
+    )?;
+    Ok(())
+}
+
+impl Plugin {
+    /// Top up the fuel, so that every call gets exactly `limits.fuel`
+    fn refuel(&mut self) -> Result<()> {
+        let fuel = self.limits.fuel;
+        let consumed = self.store.fuel_consumed().unwrap_or(0);
+        let remaining = self.fuel_added.saturating_sub(consumed);
+        if remaining < fuel {
+            self.store
+                .add_fuel(fuel - remaining)
+                .map_err(|e| failure::format_err!("{}", e))?;
+            self.fuel_added += fuel - remaining;
+        }
+        Ok(())
+    }
+}
+
+impl Plugins {
+    pub fn load(
+        &mut self,
+        name: &str,
+        wasm: &[u8],
+        capabilities: &[Capability],
+        limits: Limits,
+    ) -> Result<()> {
+        if self.plugins.iter().any(|p| p.name == name) {
+            failure::bail!("plugin `{}` already loaded", name);
+        }
+
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(|e| failure::format_err!("{}", e))?;
+
+        let mut store = Store::new(
+            &engine,
+            HostData {
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(limits.memory)
+                    .instances(1)
+                    .build(),
+                buffer: None,
+                buffer_modified: false,
+                command_args: String::new(),
+                registrations: vec![],
+                msg: None,
+                next_action_id: 0,
+                next_command_id: 0,
+            },
+        );
+        store.limiter(|data| &mut data.limits);
+        store
+            .add_fuel(limits.fuel)
+            .map_err(|e| failure::format_err!("{}", e))?;
+
+        let mut linker = Linker::new(&engine);
+        link(&mut linker, capabilities)?;
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|e| failure::format_err!("plugin `{}`: {}", name, e))?;
+
+        self.plugins.push(Plugin {
+            name: name.to_owned(),
+            store,
+            instance,
+            limits,
+            fuel_added: limits.fuel,
+            actions: vec![],
+            commands: vec![],
+        });
+        let plugin_i = self.plugins.len() - 1;
+
+        let has_init = instance
+            .get_export(&self.plugins[plugin_i].store, "init")
+            .is_some();
+        let res = if has_init {
+            self.call(plugin_i, "init", None, None, "")
+        } else {
+            Ok(CallOutput {
+                buffer: None,
+                msg: None,
+            })
+        };
+
+        if let Err(e) = res {
+            self.unload(plugin_i);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn unload(&mut self, plugin_i: usize) {
+        self.plugins.remove(plugin_i);
+        self.actions.retain(|_, a| a.plugin_i != plugin_i);
+        self.commands.retain(|_, c| c.0 != plugin_i);
+        let actions = &self.actions;
+        self.key_mappings.retain(|_, a| actions.contains_key(a));
+        for action in self.actions.values_mut() {
+            if action.plugin_i > plugin_i {
+                action.plugin_i -= 1;
+            }
+        }
+        for command in self.commands.values_mut() {
+            if command.0 > plugin_i {
+                command.0 -= 1;
+            }
+        }
+    }
+
+    pub fn actions(&self) -> &BTreeMap<String, PluginAction> {
+        &self.actions
+    }
+
+    pub fn action_for_key(&self, key: Key) -> Option<&str> {
+        self.key_mappings.get(&key).map(String::as_str)
+    }
+
+    pub fn has_command(&self, name: &str) -> bool {
+        self.commands.contains_key(name)
+    }
+
+    pub(crate) fn run_action(
+        &mut self,
+        name: &str,
+        buffer: Option<Buffer>,
+    ) -> Option<Result<CallOutput>> {
+        let action = self.actions.get(name)?.clone();
+        Some(self.call(action.plugin_i, "on_action", Some(action.id), buffer, ""))
+    }
+
+    pub(crate) fn run_command(
+        &mut self,
+        name: &str,
+        args: &str,
+        buffer: Option<Buffer>,
+    ) -> Option<Result<CallOutput>> {
+        let (plugin_i, id) = *self.commands.get(name)?;
+        Some(self.call(plugin_i, "on_command", Some(id), buffer, args))
+    }
+
+    fn call(
+        &mut self,
+        plugin_i: usize,
+        export: &str,
+        arg: Option<i32>,
+        buffer: Option<Buffer>,
+        command_args: &str,
+    ) -> Result<CallOutput> {
+        let plugin = &mut self.plugins[plugin_i];
+        plugin.refuel()?;
+
+        {
+            let data = plugin.store.data_mut();
+            data.buffer = buffer;
+            data.buffer_modified = false;
+            data.command_args = command_args.to_owned();
+            data.msg = None;
+        }
+
+        let res = match arg {
+            Some(arg) => plugin
+                .instance
+                .get_typed_func::<i32, ()>(&plugin.store, export)
+                .and_then(|f| Ok(f.call(&mut plugin.store, arg)?)),
+            None => plugin
+                .instance
+                .get_typed_func::<(), ()>(&plugin.store, export)
+                .and_then(|f| Ok(f.call(&mut plugin.store, ())?)),
+        };
+
+        let data = plugin.store.data_mut();
+        let buffer = data.buffer.take();
+        let output = CallOutput {
+            buffer: if data.buffer_modified { buffer } else { None },
+            msg: data.msg.take(),
+        };
+        let registrations = std::mem::take(&mut data.registrations);
+        let plugin_name = plugin.name.clone();
+
+        // ids were already given out, so keep the registrations even if
+        // the plugin failed afterwards
+        for registration in registrations {
+            let plugin = &mut self.plugins[plugin_i];
+            match registration {
+                Registration::Action { name, help } => {
+                    let id = plugin.actions.len() as i32;
+                    let name = format!("{}.{}", plugin.name, name);
+                    plugin.actions.push(name.clone());
+                    self.actions
+                        .insert(name, PluginAction { plugin_i, id, help });
+                }
+                Registration::KeyMapping { key, action } => {
+                    let action = format!("{}.{}", plugin.name, action);
+                    if self.actions.contains_key(&action) {
+                        self.key_mappings.insert(key, action);
+                    }
+                }
+                Registration::Command { name } => {
+                    let id = plugin.commands.len() as i32;
+                    let name = format!("{}.{}", plugin.name, name);
+                    plugin.commands.push(name.clone());
+                    self.commands.insert(name, (plugin_i, id));
+                }
+            }
+        }
+
+        res.map_err(|e| failure::format_err!("plugin `{}`: {}", plugin_name, e))?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+fn load_example(
+    state: &mut crate::State,
+    capabilities: &[Capability],
+    limits: Limits,
+) -> Result<()> {
+    let wasm = wat::parse_str(include_str!("../plugins/shout.wat")).unwrap();
+    state.load_plugin("shout", &wasm, capabilities, limits)
+}
+
+#[test]
+fn example_plugin_test() {
+    let mut state = crate::State::default();
+    state.new_scratch_buffer();
+    state.cur_buffer_mut().text = ropey::Rope::from_str("hi there");
+
+    load_example(&mut state, Capability::all(), Limits::default()).unwrap();
+    assert!(state.plugins.actions().contains_key("shout.shout"));
+    assert_eq!(
+        state.plugins.action_for_key(Key::Alt('S')),
+        Some("shout.shout")
+    );
+
+    state.handle_key(Key::Alt('S'));
+    assert_eq!(state.cur_buffer().text.to_string(), "HI THERE");
+
+    state.cur_buffer_mut().text = ropey::Rope::from_str("abc");
+    crate::mode::Command::execute(&mut state, "shout.count");
+    assert_eq!(state.msg.as_deref(), Some("3"));
+}
+
+#[test]
+fn plugin_capabilities_test() {
+    let mut state = crate::State::default();
+    assert!(load_example(&mut state, &[Capability::ReadBuffer], Limits::default()).is_err());
+    assert!(state.plugins.actions().is_empty());
+}
+
+#[test]
+fn plugin_limits_test() {
+    let mut state = crate::State::default();
+    state.new_scratch_buffer();
+
+    let limits = Limits {
+        fuel: 2_000_000,
+        ..Limits::default()
+    };
+    load_example(&mut state, Capability::all(), limits).unwrap();
+
+    // neither of these should hang or blow up, just report an error;
+    // (growing memory costs fuel too, so there must be enough of it)
+    crate::mode::Command::execute(&mut state, "shout.spin");
+    assert!(state.msg.as_ref().unwrap().contains("shout"));
+    state.msg = None;
+    crate::mode::Command::execute(&mut state, "shout.grow");
+    assert_eq!(state.msg.as_deref(), Some("grow failed"));
+    crate::mode::Command::execute(&mut state, "shout.huge");
+    assert_eq!(state.msg.as_deref(), Some("too big"));
+    assert_eq!(state.cur_buffer().text.len_chars(), 0);
+
+    // still usable afterwards
+    crate::mode::Command::execute(&mut state, "shout.count");
+    assert_eq!(state.msg.as_deref(), Some("0"));
+
+    assert!(load_example(
+        &mut crate::State::default(),
+        Capability::all(),
+        Limits {
+            memory: 1024,
+            ..Limits::default()
+        }
+    )
+    .is_err());
+}
@@ -0,0 +1,379 @@
+//! Client/server protocol
+//!
+//! Every message is sent as a `u32` (little endian) length of the rest
+//! of the message, followed by a tag byte and the payload. All the integers
+//! are `u32` little endian, `char`s are sent as their `u32` value.
+//!
+//! A client starts with `ClientMessage::Hello`, and the server replies with
+//! `ServerMessage::Welcome`, or `ServerMessage::Error` if the versions
+//! don't match.
+
+use crate::render::{Coord, Frame, Style};
+use crate::Key;
+use std::io::{self, Read, Write};
+
+pub const VERSION: u32 = 2;
+
+/// Anything bigger is most probably garbage
+const MAX_MESSAGE_LEN: usize = 64 * 1024 * 1024;
+
+/// A `char` and a `Style` without any colors
+const MIN_CELL_LEN: usize = 4 + 3;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClientMessage {
+    Hello { version: u32 },
+    Resize(Coord),
+    Key(Key),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerMessage {
+    Welcome {
+        version: u32,
+    },
+    Error(String),
+    Frame(Frame),
+    Quit,
+    /// Text to put into the clipboard of the client's terminal
+    Clipboard(String),
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn new(tag: u8) -> Self {
+        Self { buf: vec![tag] }
+    }
+
+    fn u8(&mut self, v: u8) -> &mut Self {
+        self.buf.push(v);
+        self
+    }
+
+    fn u32(&mut self, v: u32) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn usize(&mut self, v: usize) -> &mut Self {
+        self.u32(v as u32)
+    }
+
+    fn char(&mut self, ch: char) -> &mut Self {
+        self.u32(ch as u32)
+    }
+
+    fn opt_u32(&mut self, v: Option<u32>) -> &mut Self {
+        match v {
+            Some(v) => self.u8(1).u32(v),
+            None => self.u8(0),
+        }
+    }
+
+    fn coord(&mut self, coord: Coord) -> &mut Self {
+        self.usize(coord.x).usize(coord.y)
+    }
+
+    fn style(&mut self, style: Style) -> &mut Self {
+        self.opt_u32(style.fg)
+            .opt_u32(style.bg)
+            .opt_u32(style.style)
+    }
+
+    fn str(&mut self, s: &str) -> &mut Self {
+        self.usize(s.len());
+        self.buf.extend_from_slice(s.as_bytes());
+        self
+    }
+
+    fn key(&mut self, key: Key) -> &mut Self {
+        match key {
+            Key::Backspace => self.u8(0),
+            Key::Left => self.u8(1),
+            Key::Right => self.u8(2),
+            Key::Up => self.u8(3),
+            Key::Down => self.u8(4),
+            Key::Home => self.u8(5),
+            Key::End => self.u8(6),
+            Key::PageUp => self.u8(7),
+            Key::PageDown => self.u8(8),
+            Key::Delete => self.u8(9),
+            Key::Insert => self.u8(10),
+            Key::F(n) => self.u8(11).u8(n),
+            Key::Char(ch) => self.u8(12).char(ch),
+            Key::Alt(ch) => self.u8(13).char(ch),
+            Key::Ctrl(ch) => self.u8(14).char(ch),
+            Key::Null => self.u8(15),
+            Key::Esc => self.u8(16),
+            Key::__IsNotComplete => self.u8(255),
+        }
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn u8(&mut self) -> io::Result<u8> {
+        let (&v, rest) = self
+            .buf
+            .split_first()
+            .ok_or_else(|| invalid_data("message too short"))?;
+        self.buf = rest;
+        Ok(v)
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(invalid_data("message too short"));
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        self.u32().map(|v| v as usize)
+    }
+
+    fn char(&mut self) -> io::Result<char> {
+        std::char::from_u32(self.u32()?).ok_or_else(|| invalid_data("invalid char"))
+    }
+
+    fn opt_u32(&mut self) -> io::Result<Option<u32>> {
+        Ok(match self.u8()? {
+            0 => None,
+            _ => Some(self.u32()?),
+        })
+    }
+
+    fn coord(&mut self) -> io::Result<Coord> {
+        Ok(Coord {
+            x: self.usize()?,
+            y: self.usize()?,
+        })
+    }
+
+    fn style(&mut self) -> io::Result<Style> {
+        Ok(Style {
+            fg: self.opt_u32()?,
+            bg: self.opt_u32()?,
+            style: self.opt_u32()?,
+        })
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.usize()?;
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| invalid_data("invalid utf8"))
+    }
+
+    fn key(&mut self) -> io::Result<Key> {
+        Ok(match self.u8()? {
+            0 => Key::Backspace,
+            1 => Key::Left,
+            2 => Key::Right,
+            3 => Key::Up,
+            4 => Key::Down,
+            5 => Key::Home,
+            6 => Key::End,
+            7 => Key::PageUp,
+            8 => Key::PageDown,
+            9 => Key::Delete,
+            10 => Key::Insert,
+            11 => Key::F(self.u8()?),
+            12 => Key::Char(self.char()?),
+            13 => Key::Alt(self.char()?),
+            14 => Key::Ctrl(self.char()?),
+            15 => Key::Null,
+            16 => Key::Esc,
+            _ => return Err(invalid_data("unknown key")),
+        })
+    }
+
+    fn finish<T>(self, v: T) -> io::Result<T> {
+        if self.buf.is_empty() {
+            Ok(v)
+        } else {
+            Err(invalid_data("trailing data in message"))
+        }
+    }
+}
+
+impl ClientMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        match *self {
+            ClientMessage::Hello { version } => {
+                let mut e = Encoder::new(0);
+                e.u32(version);
+                e.buf
+            }
+            ClientMessage::Resize(coord) => {
+                let mut e = Encoder::new(1);
+                e.coord(coord);
+                e.buf
+            }
+            ClientMessage::Key(key) => {
+                let mut e = Encoder::new(2);
+                e.key(key);
+                e.buf
+            }
+        }
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let mut d = Decoder { buf };
+        let msg = match d.u8()? {
+            0 => ClientMessage::Hello { version: d.u32()? },
+            1 => ClientMessage::Resize(d.coord()?),
+            2 => ClientMessage::Key(d.key()?),
+            _ => return Err(invalid_data("unknown client message")),
+        };
+        d.finish(msg)
+    }
+}
+
+impl ServerMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            ServerMessage::Welcome { version } => {
+                let mut e = Encoder::new(0);
+                e.u32(*version);
+                e.buf
+            }
+            ServerMessage::Error(msg) => {
+                let mut e = Encoder::new(1);
+                e.str(msg);
+                e.buf
+            }
+            ServerMessage::Frame(frame) => {
+                let mut e = Encoder::new(2);
+                e.coord(frame.dimensions);
+                match frame.cursor {
+                    Some(cursor) => e.u8(1).coord(cursor),
+                    None => e.u8(0),
+                };
+                for &(ch, style) in &frame.cells {
+                    e.char(ch).style(style);
+                }
+                e.buf
+            }
+            ServerMessage::Quit => Encoder::new(3).buf,
+            ServerMessage::Clipboard(text) => {
+                let mut e = Encoder::new(4);
+                e.str(text);
+                e.buf
+            }
+        }
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let mut d = Decoder { buf };
+        let msg = match d.u8()? {
+            0 => ServerMessage::Welcome { version: d.u32()? },
+            1 => ServerMessage::Error(d.string()?),
+            2 => {
+                let dimensions = d.coord()?;
+                let cursor = match d.u8()? {
+                    0 => None,
+                    _ => Some(d.coord()?),
+                };
+                // don't trust the dimensions with the allocation
+                let len = dimensions
+                    .x
+                    .checked_mul(dimensions.y)
+                    .filter(|&len| len <= d.buf.len() / MIN_CELL_LEN)
+                    .ok_or_else(|| invalid_data("frame too big"))?;
+                let mut cells = Vec::with_capacity(len);
+                for _ in 0..len {
+                    cells.push((d.char()?, d.style()?));
+                }
+                ServerMessage::Frame(Frame {
+                    dimensions,
+                    cells,
+                    cursor,
+                })
+            }
+            3 => ServerMessage::Quit,
+            4 => ServerMessage::Clipboard(d.string()?),
+            _ => return Err(invalid_data("unknown server message")),
+        };
+        d.finish(msg)
+    }
+}
+
+/// Write a single, already encoded message
+pub fn write_message(w: &mut dyn Write, msg: &[u8]) -> io::Result<()> {
+    w.write_all(&(msg.len() as u32).to_le_bytes())?;
+    w.write_all(msg)?;
+    w.flush()
+}
+
+/// Read a single message, to be decoded
+pub fn read_message(r: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    r.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(invalid_data("message too long"));
+    }
+    let mut buf = vec![0u8; len];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+#[test]
+fn roundtrip_test() {
+    for msg in &[
+        ClientMessage::Hello { version: VERSION },
+        ClientMessage::Resize(Coord { x: 80, y: 25 }),
+        ClientMessage::Key(Key::Char('ż')),
+        ClientMessage::Key(Key::F(12)),
+        ClientMessage::Key(Key::Esc),
+    ] {
+        assert_eq!(&ClientMessage::decode(&msg.encode()).unwrap(), msg);
+    }
+
+    let mut frame = Frame::new(Coord { x: 2, y: 1 });
+    frame.cells[1] = (
+        'x',
+        Style {
+            fg: Some(3),
+            ..Style::default()
+        },
+    );
+    frame.cursor = Some(Coord { x: 1, y: 0 });
+
+    for msg in &[
+        ServerMessage::Welcome { version: VERSION },
+        ServerMessage::Error("oops".into()),
+        ServerMessage::Frame(frame),
+        ServerMessage::Quit,
+        ServerMessage::Clipboard("ż\n".into()),
+    ] {
+        let mut buf = vec![];
+        write_message(&mut buf, &msg.encode()).unwrap();
+        let read = read_message(&mut &buf[..]).unwrap();
+        assert_eq!(&ServerMessage::decode(&read).unwrap(), msg);
+    }
+
+    assert!(ClientMessage::decode(&[0, 1]).is_err());
+
+    // a frame of 65535x65535 cells, without them
+    let mut lying = vec![2];
+    lying.extend_from_slice(&[0xff, 0xff, 0, 0, 0xff, 0xff, 0, 0, 0]);
+    assert!(ServerMessage::decode(&lying).is_err());
+}
@@ -33,9 +33,51 @@ pub struct ColorMap {
     pub direction_marker: Style,
     pub selection: Style,
     pub special: Style,
+    pub fuzzy_match: Style,
+    pub matching_bracket: Style,
 }
 
-#[derive(Copy, Clone, Debug)]
+/// Colors shared by the frontends (ANSI 256 color palette indices)
+pub fn default_color_map() -> ColorMap {
+    ColorMap {
+        default: Style::default(),
+        actions: Style {
+            fg: Some(8),
+            bg: Some(14),
+            ..Default::default()
+        },
+        line_num: Style {
+            fg: Some(10),
+            ..Default::default()
+        },
+        direction_marker: Style {
+            fg: Some(16),
+            bg: Some(4),
+            ..Default::default()
+        },
+        selection: Style {
+            fg: Some(16),
+            bg: Some(4),
+            ..Default::default()
+        },
+        special: Style {
+            fg: Some(14),
+            ..Default::default()
+        },
+        fuzzy_match: Style {
+            fg: Some(11),
+            style: Some(1),
+            ..Default::default()
+        },
+        matching_bracket: Style {
+            fg: Some(9),
+            style: Some(1),
+            ..Default::default()
+        },
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct Coord {
     pub x: usize,
     pub y: usize,
@@ -143,7 +185,7 @@ where
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Rect {
     pub offset: Coord,
     pub dimensions: Coord,
@@ -259,3 +301,74 @@ where
         self.backend.set_cursor(coord.map(|c| c + self.rect.offset))
     }
 }
+
+/// Rendered screen contents
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub dimensions: Coord,
+    /// Row after row
+    pub cells: Vec<(char, Style)>,
+    pub cursor: Option<Coord>,
+}
+
+impl Frame {
+    pub fn new(dimensions: Coord) -> Self {
+        Self {
+            dimensions,
+            cells: vec![(' ', Style::default()); dimensions.x * dimensions.y],
+            cursor: None,
+        }
+    }
+
+    pub fn get(&self, coord: Coord) -> Option<(char, Style)> {
+        if coord.is_inside_dimensions(self.dimensions) {
+            Some(self.cells[coord.y * self.dimensions.x + coord.x])
+        } else {
+            None
+        }
+    }
+
+    /// Text in the `y`-th row
+    pub fn row_text(&self, y: usize) -> String {
+        self.cells[y * self.dimensions.x..(y + 1) * self.dimensions.x]
+            .iter()
+            .map(|(ch, _)| *ch)
+            .collect()
+    }
+}
+
+/// `Renderer` that just records a `Frame`
+pub struct FrameRenderer {
+    pub frame: Frame,
+    color_map: ColorMap,
+}
+
+impl FrameRenderer {
+    pub fn new(dimensions: Coord, color_map: ColorMap) -> Self {
+        Self {
+            frame: Frame::new(dimensions),
+            color_map,
+        }
+    }
+}
+
+impl Renderer for FrameRenderer {
+    fn color_map(&self) -> &ColorMap {
+        &self.color_map
+    }
+
+    fn dimensions(&self) -> Coord {
+        self.frame.dimensions
+    }
+
+    fn put(&mut self, coord: Coord, ch: char, style: Style) {
+        if coord.is_inside_dimensions(self.frame.dimensions) {
+            let i = coord.y * self.frame.dimensions.x + coord.x;
+            self.frame.cells[i] = (ch, style);
+        }
+    }
+
+    fn set_cursor(&mut self, coord: Option<Coord>) {
+        self.frame.cursor = coord;
+    }
+}
@@ -0,0 +1,502 @@
+//! JSON-RPC 2.0 scripting API
+//!
+//! Lets external tools drive the editor without simulating key presses.
+//! `Rpc` is transport agnostic: it takes one request and returns one
+//! response, each a single JSON document; frontends are responsible for
+//! shipping them around (eg. one per line over stdin/stdout).
+//!
+//! Buffers are identified by the numbers returned by `buffer.list`
+//! and `buffer.open`, the same as in `:ls` and `:b`. All the text
+//! positions are char indices. When the `buffer` param is missing,
+//! the current buffer is used.
+//!
+//! Methods:
+//!
+//! * `buffer.list` -> `[{id, name, path, modified, current}]`
+//! * `buffer.open {path}` -> `id`
+//! * `buffer.new` -> `id`
+//! * `buffer.switch {buffer}`
+//! * `buffer.get_text {buffer?, start?, end?}` -> `text`
+//! * `buffer.set_text {buffer?, start?, end?, text}`
+//! * `selection.get {buffer?}` -> `{primary, selections: [{anchor, cursor}]}`
+//! * `selection.set {buffer?, primary?, selections: [{anchor, cursor}]}`
+//! * `mode.get` -> `name`
+//! * `action.list` -> `[{id, help}]`
+//! * `action.run {id}`
+//! * `command.run {command}` -> message displayed by the command, if any
+//! * `events.subscribe {events}`, `events.unsubscribe {events}`
+//!
+//! Subscribed events are sent as notifications:
+//!
+//! * `buffer_changed {buffer}` - text of a buffer changed (or it was opened)
+//! * `mode_changed {mode}`
+
+use crate::action::normal;
+use crate::buffer::Buffer;
+use crate::mode::Command;
+use crate::selection::Selection;
+use crate::state::BufferState;
+use crate::{Idx, State};
+use ropey::Rope;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Request was fine, but the editor couldn't do it
+const EDITOR_ERROR: i64 = -32000;
+
+const EVENTS: &[&str] = &["buffer_changed", "mode_changed"];
+
+#[derive(Debug)]
+struct Error {
+    code: i64,
+    message: String,
+}
+
+impl Error {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+type RpcResult<T> = std::result::Result<T, Error>;
+
+/// Request params, with helpers to pull typed fields out of them
+struct Params(Map<String, Value>);
+
+impl Params {
+    fn opt_usize(&self, name: &str) -> RpcResult<Option<usize>> {
+        match self.0.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(v) => v
+                .as_u64()
+                .map(|v| Some(v as usize))
+                .ok_or_else(|| Error::invalid_params(format!("`{}` must be a number", name))),
+        }
+    }
+
+    fn opt_str(&self, name: &str) -> RpcResult<Option<&str>> {
+        match self.0.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(v) => v
+                .as_str()
+                .map(Some)
+                .ok_or_else(|| Error::invalid_params(format!("`{}` must be a string", name))),
+        }
+    }
+
+    fn str(&self, name: &str) -> RpcResult<&str> {
+        self.opt_str(name)?
+            .ok_or_else(|| Error::invalid_params(format!("missing `{}`", name)))
+    }
+
+    fn array(&self, name: &str) -> RpcResult<&Vec<Value>> {
+        self.0
+            .get(name)
+            .and_then(Value::as_array)
+            .ok_or_else(|| Error::invalid_params(format!("`{}` must be an array", name)))
+    }
+
+    fn events(&self) -> RpcResult<Vec<&'static str>> {
+        self.array("events")?
+            .iter()
+            .map(|event| {
+                event
+                    .as_str()
+                    .and_then(|event| EVENTS.iter().find(|e| **e == event))
+                    .cloned()
+                    .ok_or_else(|| Error::invalid_params(format!("unknown event: {}", event)))
+            })
+            .collect()
+    }
+}
+
+fn selection_from_json(v: &Value, text: &Rope) -> RpcResult<Selection> {
+    let idx = |name| {
+        v.get(name)
+            .and_then(Value::as_u64)
+            .map(|i| Idx(i as usize))
+            .filter(|i| i.0 <= text.len_chars())
+            .ok_or_else(|| Error::invalid_params(format!("invalid selection `{}`", name)))
+    };
+    Ok(Selection::new(idx("anchor")?, idx("cursor")?, text))
+}
+
+/// Scripting API session
+///
+/// Keeps track of the event subscriptions, and of what was reported
+/// to the client so far.
+#[derive(Default)]
+pub struct Rpc {
+    subscriptions: BTreeSet<&'static str>,
+    /// `Buffer::version` of every buffer as of the last `poll_events`
+    last_versions: Vec<(usize, u64)>,
+    last_mode: String,
+}
+
+impl Rpc {
+    pub fn new(state: &State) -> Self {
+        let mut rpc = Self::default();
+        rpc.poll_events(state);
+        rpc
+    }
+
+    /// Handle one request
+    ///
+    /// Returns the response, unless the request was a notification.
+    pub fn handle(&mut self, state: &mut State, request: &str) -> Option<String> {
+        let request: Value = match serde_json::from_str(request) {
+            Ok(request) => request,
+            Err(e) => {
+                return Some(Self::response(
+                    Value::Null,
+                    Err(Error::new(PARSE_ERROR, e.to_string())),
+                ))
+            }
+        };
+
+        let id = request.get("id").cloned();
+        let res = self.handle_request(state, &request);
+        id.map(|id| Self::response(id, res))
+    }
+
+    fn response(id: Value, res: RpcResult<Value>) -> String {
+        match res {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": e.code, "message": e.message },
+            }),
+        }
+        .to_string()
+    }
+
+    fn handle_request(&mut self, state: &mut State, request: &Value) -> RpcResult<Value> {
+        let method = request
+            .get("method")
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::new(INVALID_REQUEST, "missing method"))?;
+        let params = match request.get("params") {
+            None | Some(Value::Null) => Params(Map::new()),
+            Some(Value::Object(params)) => Params(params.clone()),
+            Some(_) => return Err(Error::new(INVALID_REQUEST, "params must be an object")),
+        };
+
+        match method {
+            "buffer.list" => Ok(state
+                .buffers
+                .iter()
+                .map(|(i, buffer_state)| {
+                    json!({
+                        "id": State::buffer_num(i),
+                        "name": buffer_state.display_name(),
+                        "path": buffer_state.path().map(|p| p.display().to_string()),
+                        "modified": buffer_state.is_modified(),
+                        "current": state.cur_buffer_i == Some(i),
+                    })
+                })
+                .collect()),
+            "buffer.open" => {
+                let path = PathBuf::from(params.str("path")?);
+                state.msg = None;
+                state.open_buffer(&path);
+                match state.cur_buffer_state_opt() {
+                    Some(buffer_state) if buffer_state.path() == Some(path.as_path()) => {
+                        Ok(json!(state.cur_buffer_i.map(State::buffer_num)))
+                    }
+                    _ => Err(Error::new(
+                        EDITOR_ERROR,
+                        state.msg.take().unwrap_or_else(|| "can't open".into()),
+                    )),
+                }
+            }
+            "buffer.new" => {
+                state.new_scratch_buffer();
+                Ok(json!(state.cur_buffer_i.map(State::buffer_num)))
+            }
+            "buffer.switch" => {
+                let i = Self::buffer_i(state, &params)?;
+                state.set_cur_buffer(i);
+                Ok(Value::Null)
+            }
+            "buffer.get_text" => {
+                let buffer = &Self::buffer_state(state, &params)?.buffer;
+                let (start, end) = Self::range(buffer, &params)?;
+                Ok(json!(buffer.text.slice(start.0..end.0).to_string()))
+            }
+            "buffer.set_text" => {
+                let text = params.str("text")?.to_owned();
+                let buffer_state = Self::buffer_state_mut(state, &params)?;
+                let (start, end) = Self::range(&buffer_state.buffer, &params)?;
+                buffer_state.maybe_commit_undo_point();
+                buffer_state.buffer.replace_range(start..end, &text);
+                buffer_state.maybe_commit_undo_point();
+                Ok(Value::Null)
+            }
+            "selection.get" => {
+                let selection = &Self::buffer_state(state, &params)?.buffer.selection;
+                Ok(json!({
+                    "primary": selection.primary,
+                    "selections": selection
+                        .selections
+                        .iter()
+                        .map(|sel| json!({ "anchor": sel.anchor.0, "cursor": sel.cursor.0 }))
+                        .collect::<Vec<_>>(),
+                }))
+            }
+            "selection.set" => {
+                let buffer_state = Self::buffer_state_mut(state, &params)?;
+                let text = &buffer_state.buffer.text;
+                let selections = params
+                    .array("selections")?
+                    .iter()
+                    .map(|sel| selection_from_json(sel, text))
+                    .collect::<RpcResult<Vec<_>>>()?;
+                let primary = params.opt_usize("primary")?.unwrap_or(0);
+                if primary >= selections.len() {
+                    return Err(Error::invalid_params("invalid `primary`"));
+                }
+
+                let selection = &mut buffer_state.buffer.selection;
+                selection.selections = selections;
+                selection.primary = primary;
+                selection.clear_cursor_column();
+                selection.normalize();
+                buffer_state.maybe_commit_undo_point();
+                Ok(Value::Null)
+            }
+            "mode.get" => Ok(json!(state.mode_name())),
+            "action.list" => Ok(normal::actions()
+                .iter()
+                .map(|(id, action)| json!({ "id": id, "help": action.help() }))
+                .collect()),
+            "action.run" => {
+                let action = normal::actions()
+                    .get(params.str("id")?)
+                    .ok_or_else(|| Error::invalid_params("action not found"))?;
+                if state.cur_buffer_opt().is_none() {
+                    return Err(Error::new(EDITOR_ERROR, "no buffer"));
+                }
+                if let Some(b) = state.cur_buffer_state_mut_opt() {
+                    b.maybe_commit_undo_point();
+                }
+                action.execute(state);
+                if let Some(b) = state.cur_buffer_state_mut_opt() {
+                    b.maybe_commit_undo_point();
+                }
+                Ok(Value::Null)
+            }
+            "command.run" => {
+                let command = params.str("command")?;
+                state.msg = None;
+                Command::execute(state, command.trim_start_matches(':'));
+                Ok(json!(state.msg))
+            }
+            "events.subscribe" => {
+                self.subscriptions.extend(params.events()?);
+                Ok(Value::Null)
+            }
+            "events.unsubscribe" => {
+                for event in params.events()? {
+                    self.subscriptions.remove(event);
+                }
+                Ok(Value::Null)
+            }
+            _ => Err(Error::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method: {}", method),
+            )),
+        }
+    }
+
+    fn buffer_i(state: &State, params: &Params) -> RpcResult<usize> {
+        match params.opt_usize("buffer")? {
+            Some(num) => state
+                .buffer_by_num(num)
+                .ok_or_else(|| Error::invalid_params("no such buffer")),
+            None => state
+                .cur_buffer_i
+                .ok_or_else(|| Error::new(EDITOR_ERROR, "no buffer")),
+        }
+    }
+
+    fn buffer_state<'a>(state: &'a State, params: &Params) -> RpcResult<&'a BufferState> {
+        Ok(&state.buffers[Self::buffer_i(state, params)?])
+    }
+
+    fn buffer_state_mut<'a>(
+        state: &'a mut State,
+        params: &Params,
+    ) -> RpcResult<&'a mut BufferState> {
+        let i = Self::buffer_i(state, params)?;
+        Ok(&mut state.buffers[i])
+    }
+
+    /// `start`..`end` params, defaulting to the whole text
+    fn range(buffer: &Buffer, params: &Params) -> RpcResult<(Idx, Idx)> {
+        let len = buffer.text.len_chars();
+        let start = params.opt_usize("start")?.unwrap_or(0);
+        let end = params.opt_usize("end")?.unwrap_or(len);
+        if start > end || end > len {
+            return Err(Error::invalid_params("invalid range"));
+        }
+        Ok((Idx(start), Idx(end)))
+    }
+
+    /// Notifications about everything subscribed that changed since the last call
+    ///
+    /// Should be called after anything that could have modified the `state`.
+    pub fn poll_events(&mut self, state: &State) -> Vec<String> {
+        let mut events = vec![];
+
+        let versions: Vec<_> = state
+            .buffers
+            .iter()
+            .map(|(i, buffer_state)| (i, buffer_state.buffer.version()))
+            .collect();
+        for (i, version) in &versions {
+            let changed = self
+                .last_versions
+                .iter()
+                .find(|(last_i, _)| last_i == i)
+                .map(|(_, last_version)| last_version != version)
+                .unwrap_or(true);
+            if changed {
+                events.push(("buffer_changed", json!({ "buffer": State::buffer_num(*i) })));
+            }
+        }
+        self.last_versions = versions;
+
+        let mode = state.mode_name();
+        if mode != self.last_mode {
+            self.last_mode = mode.to_owned();
+            events.push(("mode_changed", json!({ "mode": mode })));
+        }
+
+        events
+            .into_iter()
+            .filter(|(event, _)| self.subscriptions.contains(event))
+            .map(|(event, params)| {
+                json!({ "jsonrpc": "2.0", "method": event, "params": params }).to_string()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+fn call(rpc: &mut Rpc, state: &mut State, method: &str, params: Value) -> Value {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let response: Value =
+        serde_json::from_str(&rpc.handle(state, &request.to_string()).unwrap()).unwrap();
+    assert_eq!(response["id"], json!(1));
+    response
+}
+
+#[test]
+fn rpc_edit_test() {
+    let mut state = State::default();
+    let mut rpc = Rpc::new(&state);
+
+    let buffer = call(&mut rpc, &mut state, "buffer.new", json!({}))["result"].clone();
+    // numbered like in `:ls`
+    assert_eq!(buffer, json!(1));
+    assert_eq!(
+        call(&mut rpc, &mut state, "buffer.list", json!({}))["result"][0]["id"],
+        buffer
+    );
+    call(
+        &mut rpc,
+        &mut state,
+        "events.subscribe",
+        json!({ "events": ["buffer_changed", "mode_changed"] }),
+    );
+    call(
+        &mut rpc,
+        &mut state,
+        "buffer.set_text",
+        json!({ "buffer": buffer, "text": "hello world" }),
+    );
+    call(
+        &mut rpc,
+        &mut state,
+        "buffer.set_text",
+        json!({ "start": 0, "end": 5, "text": "bye" }),
+    );
+    assert_eq!(
+        call(
+            &mut rpc,
+            &mut state,
+            "buffer.get_text",
+            json!({ "start": 4 })
+        )["result"],
+        json!("world")
+    );
+    assert_eq!(
+        rpc.poll_events(&state),
+        vec![
+            json!({ "jsonrpc": "2.0", "method": "buffer_changed", "params": { "buffer": buffer } })
+                .to_string()
+        ]
+    );
+
+    call(
+        &mut rpc,
+        &mut state,
+        "selection.set",
+        json!({ "selections": [{ "anchor": 0, "cursor": 3 }, { "anchor": 4, "cursor": 9 }] }),
+    );
+    assert_eq!(
+        call(&mut rpc, &mut state, "selection.get", json!({}))["result"]["selections"][1],
+        json!({ "anchor": 4, "cursor": 9 })
+    );
+
+    call(
+        &mut rpc,
+        &mut state,
+        "action.run",
+        json!({ "id": "Insert" }),
+    );
+    assert_eq!(
+        rpc.poll_events(&state),
+        vec![
+            json!({ "jsonrpc": "2.0", "method": "mode_changed", "params": { "mode": "insert" } })
+                .to_string()
+        ]
+    );
+
+    assert_eq!(
+        call(
+            &mut rpc,
+            &mut state,
+            "command.run",
+            json!({ "command": "nope" })
+        )["result"],
+        json!("unrecognized command: nope")
+    );
+    assert_eq!(
+        call(&mut rpc, &mut state, "buffer.nope", json!({}))["error"]["code"],
+        json!(METHOD_NOT_FOUND)
+    );
+    assert_eq!(
+        call(
+            &mut rpc,
+            &mut state,
+            "buffer.get_text",
+            json!({ "end": 100 })
+        )["error"]["code"],
+        json!(INVALID_PARAMS)
+    );
+    assert!(rpc.handle(&mut state, "{").unwrap().contains("-32700"));
+}
@@ -0,0 +1,335 @@
+//! Editor session shared by many clients
+//!
+//! `Server` doesn't know anything about sockets; it just gets messages
+//! from clients, and writes the replies to whatever it was given
+//! for each of them.
+
+use crate::protocol::{self, ClientMessage, ServerMessage};
+use crate::render::{ColorMap, Coord, FrameRenderer};
+use crate::state::Context;
+use crate::State;
+use slab::Slab;
+use std::io::Write;
+
+pub type ClientId = usize;
+
+struct Client {
+    writer: Box<dyn Write>,
+    /// Set after a successful `Hello`
+    greeted: bool,
+    dimensions: Option<Coord>,
+    /// Own mode and windows; swapped with the one in the `State`
+    /// while the client is the active one
+    context: Context,
+}
+
+pub struct Server {
+    state: State,
+    color_map: ColorMap,
+    clients: Slab<Client>,
+    /// Client whose `Context` is in the `State`
+    active: Option<ClientId>,
+}
+
+impl Server {
+    pub fn new(state: State, color_map: ColorMap) -> Self {
+        Self {
+            state,
+            color_map,
+            clients: Slab::new(),
+            active: None,
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.is_finished()
+    }
+
+    pub fn add_client(&mut self, writer: impl Write + 'static) -> ClientId {
+        let context = self.state.new_context();
+        self.clients.insert(Client {
+            writer: Box::new(writer),
+            greeted: false,
+            dimensions: None,
+            context,
+        })
+    }
+
+    pub fn remove_client(&mut self, id: ClientId) {
+        if self.clients.contains(id) {
+            let mut client = self.clients.remove(id);
+            if self.active == Some(id) {
+                self.active = None;
+                self.state.swap_context(&mut client.context);
+            }
+            self.state.drop_context(client.context);
+        }
+    }
+
+    /// Put the `Context` of the client `id` into the `State`
+    fn activate(&mut self, id: ClientId) {
+        if self.active == Some(id) {
+            return;
+        }
+        if let Some(active) = self.active.take() {
+            self.state.swap_context(&mut self.clients[active].context);
+        }
+        self.state.swap_context(&mut self.clients[id].context);
+        self.active = Some(id);
+    }
+
+    pub fn has_client(&self, id: ClientId) -> bool {
+        self.clients.contains(id)
+    }
+
+    pub fn handle_message(&mut self, id: ClientId, msg: ClientMessage) {
+        if !self.clients.contains(id) {
+            return;
+        }
+
+        match msg {
+            ClientMessage::Hello { version } => {
+                if version == protocol::VERSION {
+                    self.clients[id].greeted = true;
+                    self.send(id, &ServerMessage::Welcome { version });
+                } else {
+                    self.send(
+                        id,
+                        &ServerMessage::Error(format!(
+                            "protocol version mismatch: server {}, client {}",
+                            protocol::VERSION,
+                            version
+                        )),
+                    );
+                    self.remove_client(id);
+                }
+            }
+            _ if !self.clients[id].greeted => {
+                self.send(id, &ServerMessage::Error("expected hello".into()));
+                self.remove_client(id);
+            }
+            ClientMessage::Resize(dimensions) => {
+                self.clients[id].dimensions = Some(dimensions);
+                self.send_frame(id);
+            }
+            ClientMessage::Key(key) => {
+                self.activate(id);
+                self.state.handle_key(key);
+                // the terminal of whoever copied it
+                if let Some(text) = self.state.take_copied() {
+                    self.send(id, &ServerMessage::Clipboard(text));
+                }
+                if self.state.is_finished() {
+                    self.broadcast(&ServerMessage::Quit);
+                } else {
+                    self.send_frames();
+                }
+            }
+        }
+    }
+
+    /// Send a message, dropping the client on any error
+    ///
+    /// That includes a timed out write of a client not reading them.
+    fn send(&mut self, id: ClientId, msg: &ServerMessage) {
+        let res = match self.clients.get_mut(id) {
+            Some(client) => protocol::write_message(&mut client.writer, &msg.encode()),
+            // dropped already, earlier in the same round
+            None => return,
+        };
+        if res.is_err() {
+            self.remove_client(id);
+        }
+    }
+
+    fn broadcast(&mut self, msg: &ServerMessage) {
+        let ids: Vec<_> = self.clients.iter().map(|(id, _)| id).collect();
+        for id in ids {
+            self.send(id, msg);
+        }
+    }
+
+    fn send_frame(&mut self, id: ClientId) {
+        let dimensions = match self.clients.get(id) {
+            Some(&Client {
+                dimensions: Some(dimensions),
+                greeted: true,
+                ..
+            }) if dimensions.x > 0 && dimensions.y > 1 => dimensions,
+            _ => return,
+        };
+
+        self.activate(id);
+        let mut render = FrameRenderer::new(dimensions, self.color_map);
+        self.state.render(&mut render);
+        self.send(id, &ServerMessage::Frame(render.frame));
+    }
+
+    /// Send a fresh frame to all the clients
+    pub fn send_frames(&mut self) {
+        let ids: Vec<_> = self.clients.iter().map(|(id, _)| id).collect();
+        for id in ids {
+            self.send_frame(id);
+        }
+    }
+}
+
+/// In-process client, reading what the server wrote to it
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct FakeClient {
+    written: std::rc::Rc<std::cell::RefCell<Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl std::io::Write for FakeClient {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.written.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl FakeClient {
+    fn received(&self) -> Vec<ServerMessage> {
+        let written = std::mem::take(&mut *self.written.borrow_mut());
+        let mut reader = &written[..];
+        let mut msgs = vec![];
+        while !reader.is_empty() {
+            msgs.push(
+                ServerMessage::decode(&protocol::read_message(&mut reader).unwrap()).unwrap(),
+            );
+        }
+        msgs
+    }
+
+    fn last_frame(&self) -> crate::render::Frame {
+        match self.received().pop() {
+            Some(ServerMessage::Frame(frame)) => frame,
+            other => panic!("expected frame, got: {:?}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+fn hello() -> ClientMessage {
+    ClientMessage::Hello {
+        version: protocol::VERSION,
+    }
+}
+
+#[test]
+fn two_clients_share_session_test() {
+    let mut server = Server::new(State::default(), ColorMap::default());
+
+    let a = FakeClient::default();
+    let b = FakeClient::default();
+    let a_id = server.add_client(a.clone());
+    let b_id = server.add_client(b.clone());
+
+    server.handle_message(a_id, hello());
+    server.handle_message(b_id, hello());
+    assert_eq!(
+        a.received(),
+        vec![ServerMessage::Welcome {
+            version: protocol::VERSION
+        }]
+    );
+    b.received();
+
+    server.handle_message(a_id, ClientMessage::Resize(Coord { x: 40, y: 10 }));
+    server.handle_message(b_id, ClientMessage::Resize(Coord { x: 20, y: 5 }));
+    assert_eq!(a.last_frame().dimensions, Coord { x: 40, y: 10 });
+    assert_eq!(b.last_frame().dimensions, Coord { x: 20, y: 5 });
+
+    let keys = |server: &mut Server, id, keys: &str| {
+        for ch in keys.chars() {
+            let key = if ch == '\x1b' {
+                crate::Key::Esc
+            } else {
+                crate::Key::Char(ch)
+            };
+            server.handle_message(id, ClientMessage::Key(key));
+        }
+    };
+    let status = |frame: crate::render::Frame| frame.row_text(frame.dimensions.y - 1);
+
+    // `a` stays in insert mode while `b` moves and edits on its own
+    keys(&mut server, a_id, ":new\nihello");
+    keys(&mut server, b_id, ":b 1\nhhhhhix\x1b");
+    keys(&mut server, a_id, "!");
+
+    assert_eq!(server.state().cur_buffer().text.to_string(), "xhello!");
+    let a_frame = a.last_frame();
+    assert!(a_frame.row_text(0).contains("xhello!"));
+    assert!(status(a_frame).contains("inse"));
+    server.handle_message(b_id, ClientMessage::Resize(Coord { x: 20, y: 5 }));
+    let b_frame = b.last_frame();
+    assert!(b_frame.row_text(0).contains("xhello!"));
+    assert!(status(b_frame).contains("norm"));
+
+    server.handle_message(b_id, ClientMessage::Key(crate::Key::Alt('y')));
+    assert_eq!(b.received()[0], ServerMessage::Clipboard("x".into()));
+    assert_eq!(a.received().len(), 1);
+
+    for ch in ":q\n".chars() {
+        server.handle_message(b_id, ClientMessage::Key(crate::Key::Char(ch)));
+    }
+    assert!(server.is_finished());
+    assert_eq!(a.received().pop(), Some(ServerMessage::Quit));
+}
+
+#[test]
+fn version_mismatch_test() {
+    let mut server = Server::new(State::default(), ColorMap::default());
+    let client = FakeClient::default();
+    let id = server.add_client(client.clone());
+
+    server.handle_message(id, ClientMessage::Hello { version: 0 });
+    match client.received().as_slice() {
+        [ServerMessage::Error(_)] => {}
+        other => panic!("unexpected: {:?}", other),
+    }
+    assert!(!server.has_client(id));
+}
+
+/// Client that stopped reading, so that every write times out
+#[cfg(test)]
+struct StalledClient;
+
+#[cfg(test)]
+impl std::io::Write for StalledClient {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::TimedOut.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn stalled_client_test() {
+    let mut server = Server::new(State::default(), ColorMap::default());
+    let a = FakeClient::default();
+    let a_id = server.add_client(a.clone());
+    let b_id = server.add_client(StalledClient);
+
+    server.handle_message(a_id, hello());
+    server.handle_message(b_id, hello());
+    assert!(!server.has_client(b_id));
+
+    server.handle_message(a_id, ClientMessage::Resize(Coord { x: 40, y: 10 }));
+    assert_eq!(a.last_frame().dimensions, Coord { x: 40, y: 10 });
+}
@@ -1,13 +1,15 @@
 use crate::buffer::Buffer;
 use crate::buffer::VisualSelection;
 use crate::mode::{self, Mode};
+use crate::plugin::{self, Capability, Plugins};
 use crate::Idx;
 use crate::Key;
 use default::default;
 use ropey::Rope;
 
-use crate::render::{self, Coord, Renderer};
-use crate::{buffer, position};
+use crate::render::{self, Coord, Rect, Renderer};
+use crate::window::{self, Direction, Layout, SplitDirection, Window};
+use crate::{buffer, editorconfig, filetype, position};
 use std::cell::RefCell;
 use std::cmp::min;
 use std::io;
@@ -16,6 +18,8 @@ use std::sync::Arc;
 
 use slab::Slab;
 
+const RECENT_FILES_MAX: usize = 100;
+
 #[derive(Clone)]
 pub struct BufferState {
     pub(crate) buffer: Buffer,
@@ -23,6 +27,8 @@ pub struct BufferState {
     pub(crate) buffer_history_undo_i: Option<usize>,
 
     path: Option<PathBuf>,
+    /// Text as it was last read or written
+    saved_text: Rope,
 }
 
 impl BufferState {
@@ -69,36 +75,140 @@ impl BufferState {
         };
 
         self.buffer_history_undo_i = Some(i);
+        self.restore(i);
+    }
+
+    /// Bring back the undo point `i`, keeping where the other windows are
+    fn restore(&mut self, i: usize) {
+        let window_selections = std::mem::replace(&mut self.buffer.window_selections, default());
         self.buffer = self.buffer_history[i].clone();
+        self.buffer.window_selections = window_selections;
+        for selection in self.buffer.window_selections.values_mut() {
+            selection.trim_to_text(&self.buffer.text);
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Name to display in the lists of buffers
+    pub fn display_name(&self) -> String {
+        self.path()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "*scratch*".into())
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.buffer.text != self.saved_text
     }
 
     pub(crate) fn redo(&mut self, times: usize) {
         if let Some(undo_i) = self.buffer_history_undo_i.as_mut() {
             let new_i = min(undo_i.saturating_add(times), self.buffer_history.len() - 1);
             *undo_i = new_i;
-            self.buffer = self.buffer_history[new_i].clone();
+            self.restore(new_i);
         }
     }
 }
 
+fn parse_bool_option(value: &str) -> Option<bool> {
+    match value {
+        "on" | "true" | "yes" => Some(true),
+        "off" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Keys of an edit, for `.` to repeat
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Edit {
+    pub(crate) num_prefix: Option<usize>,
+    pub(crate) keys: Vec<Key>,
+}
+
+/// What every client of a shared session (see `server`) has for itself:
+/// the mode, and the windows
+///
+/// The `State` holds the one of the client being served, and
+/// `swap_context` switches to another.
+pub struct Context {
+    mode: Option<Box<dyn Mode + 'static>>,
+    layout: Layout,
+    cur_window_i: usize,
+    last_window_rects: RefCell<Vec<(usize, Rect)>>,
+    num_prefix: Option<usize>,
+    recording_edit: Option<Edit>,
+    msg: Option<String>,
+}
+
+/// Type of `State::format_handler`
+type FormatHandler = dyn Fn(&[String], &Rope) -> io::Result<Rope>;
+
+/// Type of `State::clipboard_copy_handler`
+type ClipboardCopyHandler = dyn Fn(&str) -> io::Result<()>;
+
 /// The editor state
 pub struct State {
     pub(crate) quit: bool,
     mode: Option<Box<dyn Mode + 'static>>,
     pub(crate) yanked: Vec<Rope>,
+    /// Put into the clipboard since the last `take_copied`
+    copied: Option<String>,
 
     pub(crate) msg: Option<String>,
 
     pub(crate) read_handler: Arc<dyn Fn(&Path) -> io::Result<Rope>>,
     pub(crate) write_handler: Arc<dyn Fn(&Path, &Rope) -> io::Result<()>>,
-    pub(crate) find_handler: Arc<dyn Fn(&str) -> io::Result<Vec<PathBuf>>>,
-
-    buffers: Slab<BufferState>,
-    cur_buffer_i: Option<usize>,
+    /// Returns the files that `mode::Picker::find_file` can pick from,
+    /// after the first `start` ones
+    ///
+    /// The list might be still growing (eg. when walking the directory
+    /// tree in the background), in which case consecutive calls
+    /// must only append to it. A `start` of 0 comes from a finder that
+    /// hasn't seen any yet, so it's free to start a new list.
+    pub(crate) find_handler: Arc<dyn Fn(usize) -> io::Result<Vec<PathBuf>>>,
+    /// Runs a formatter command (`FileType::formatter`) with the text
+    /// on stdin, and returns its stdout
+    pub(crate) format_handler: Arc<FormatHandler>,
+    /// Puts text into the system clipboard
+    pub(crate) clipboard_copy_handler: Arc<ClipboardCopyHandler>,
+    /// Gets the text from the system clipboard
+    pub(crate) clipboard_paste_handler: Arc<dyn Fn() -> io::Result<String>>,
+
+    pub(crate) buffers: Slab<BufferState>,
+    pub(crate) cur_buffer_i: Option<usize>,
+    /// Previously active buffer
+    alt_buffer_i: Option<usize>,
+
+    /// Most recently opened first
+    pub(crate) recent_files: Vec<PathBuf>,
+
+    windows: Slab<Window>,
+    layout: Layout,
+    cur_window_i: usize,
+    /// Windows as they were last rendered
+    last_window_rects: RefCell<Vec<(usize, Rect)>>,
 
     pub last_visual_cursor_coord: RefCell<Option<Coord>>,
 
     pub num_prefix: Option<usize>,
+
+    /// Last complete edit
+    pub(crate) last_edit: Option<Edit>,
+    /// Edit that is being recorded (eg. during an insert mode session)
+    pub(crate) recording_edit: Option<Edit>,
+    /// Last `f`/`t` char search, for `Alt-.` to repeat
+    pub(crate) last_find: Option<buffer::FindChar>,
+    /// Keys to handle after the current one (eg. to repeat an edit)
+    pub(crate) pending_keys: Vec<Key>,
+
+    /// Insert closing brackets and quotes automatically
+    pub(crate) auto_pairs: bool,
+    /// Run the formatter of the file type on `write_buffer`
+    pub(crate) format_on_save: bool,
+
+    pub(crate) plugins: Plugins,
 }
 
 impl State {
@@ -144,7 +254,8 @@ impl State {
         }
 
         if let Some(found) = found {
-            self.cur_buffer_i = Some(found);
+            self.set_cur_buffer(found);
+            self.add_recent_file(path);
             return;
         }
 
@@ -156,21 +267,264 @@ impl State {
             Ok(rope) => rope,
         };
 
-        let entry = self.buffers.vacant_entry();
+        let props = self.editorconfig_for(path);
+        let mut buffer = Buffer::from_text(props.decode(rope));
+        buffer.filetype = filetype::from_path(Some(path));
+        buffer.detect_indent();
+        buffer.apply_editorconfig(&props);
 
-        self.cur_buffer_i = Some(entry.key());
-        entry.insert(BufferState {
+        let i = self.buffers.insert(BufferState {
             path: Some(path.to_owned()),
-            buffer: Buffer::from_text(rope),
+            saved_text: buffer.text.clone(),
+            buffer,
             ..default()
         });
+        self.set_cur_buffer(i);
+        self.add_recent_file(path);
+    }
+
+    /// Open a new buffer, not associated with any path
+    pub fn new_scratch_buffer(&mut self) {
+        let i = self.buffers.insert(default());
+        self.set_cur_buffer(i);
+    }
+
+    fn add_recent_file(&mut self, path: &Path) {
+        self.recent_files.retain(|p| p != path);
+        self.recent_files.insert(0, path.to_owned());
+        self.recent_files.truncate(RECENT_FILES_MAX);
+    }
+
+    pub(crate) fn set_cur_buffer(&mut self, i: usize) {
+        if self.buffers.contains(i) && self.cur_buffer_i != Some(i) {
+            self.alt_buffer_i = self.cur_buffer_i;
+            self.cur_buffer_i = Some(i);
+        }
+    }
+
+    /// Switch to the previously active buffer
+    pub fn buffer_alternate(&mut self) {
+        if let Some(alt_buffer_i) = self.alt_buffer_i {
+            self.set_cur_buffer(alt_buffer_i);
+        } else {
+            self.msg = Some("No alternate buffer".into());
+        }
+    }
+
+    /// Number of the buffer `i` for the user, in `:ls`, `:b` and the rpc
+    pub(crate) fn buffer_num(i: usize) -> usize {
+        i + 1
+    }
+
+    /// Buffer with the number `num`, see `buffer_num`
+    pub(crate) fn buffer_by_num(&self, num: usize) -> Option<usize> {
+        num.checked_sub(1).filter(|&i| self.buffers.contains(i))
+    }
+
+    /// Switch to a buffer by its number, or (a part of) its name
+    pub fn buffer_switch(&mut self, name: &str) {
+        if let Ok(num) = name.parse::<usize>() {
+            if let Some(i) = self.buffer_by_num(num) {
+                self.set_cur_buffer(i);
+            } else {
+                self.msg = Some(format!("No buffer number {}", num));
+            }
+            return;
+        }
+
+        let matching: Vec<_> = self
+            .buffers
+            .iter()
+            .map(|(i, b)| (i, b.display_name()))
+            .filter(|(_, display_name)| display_name.contains(name))
+            .collect();
+
+        // a full name or file name match wins over any partial ones
+        let exact = matching
+            .iter()
+            .find(|(_, display_name)| {
+                display_name == name
+                    || Path::new(display_name).file_name() == Some(std::ffi::OsStr::new(name))
+            })
+            .map(|&(i, _)| i);
+
+        if let Some(i) = exact.or_else(|| {
+            if matching.len() == 1 {
+                Some(matching[0].0)
+            } else {
+                None
+            }
+        }) {
+            self.set_cur_buffer(i);
+        } else if matching.is_empty() {
+            self.msg = Some(format!("No buffer matching: {}", name));
+        } else {
+            self.msg = Some(format!("More than one buffer matching: {}", name));
+        }
+    }
+
+    /// Put the list of buffers in the message
+    pub fn buffer_list(&mut self) {
+        let list: Vec<_> = self
+            .buffers
+            .iter()
+            .map(|(i, b)| {
+                format!(
+                    "{:>3} {} {}{}",
+                    Self::buffer_num(i),
+                    if Some(i) == self.cur_buffer_i {
+                        '%'
+                    } else if Some(i) == self.alt_buffer_i {
+                        '#'
+                    } else {
+                        ' '
+                    },
+                    b.display_name(),
+                    if b.is_modified() { " [+]" } else { "" },
+                )
+            })
+            .collect();
+
+        self.msg = Some(if list.is_empty() {
+            "No buffers".into()
+        } else {
+            list.join("\n")
+        });
+    }
+
+    /// `.editorconfig` properties of the file at `path`
+    fn editorconfig_for(&self, path: &Path) -> editorconfig::Properties {
+        let path = match std::env::current_dir() {
+            Ok(dir) => dir.join(path),
+            Err(_) => path.to_owned(),
+        };
+        editorconfig::properties_for(&path, &*self.read_handler)
+    }
+
+    /// Yank the selections, and put them into the system clipboard,
+    /// joined with newlines
+    pub fn yank_to_clipboard(&mut self) {
+        self.yanked = self.cur_buffer_mut().yank();
+        let text = self
+            .yanked
+            .iter()
+            .map(|rope| rope.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let Err(e) = (self.clipboard_copy_handler)(&text) {
+            self.msg = Some(format!("clipboard: {}", e));
+        }
+        self.copied = Some(text);
+    }
+
+    /// Text put into the clipboard since the last call
+    ///
+    /// For the frontends owning a terminal, to set its clipboard too
+    /// (eg. with OSC 52), as the handler can't know which one that is.
+    pub fn take_copied(&mut self) -> Option<String> {
+        self.copied.take()
+    }
+
+    /// What to paste from the system clipboard, one for every selection
+    ///
+    /// If the clipboard still holds what `yank_to_clipboard` put there,
+    /// the selections get their own parts back, like with `paste`.
+    pub(crate) fn clipboard_yanked(&mut self) -> Option<Vec<Rope>> {
+        let text = match (self.clipboard_paste_handler)() {
+            Ok(text) => text,
+            Err(e) => {
+                self.msg = Some(format!("clipboard: {}", e));
+                return None;
+            }
+        };
+        let yanked_text = self
+            .yanked
+            .iter()
+            .map(|rope| rope.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !self.yanked.is_empty() && text == yanked_text {
+            return Some(self.yanked.clone());
+        }
+        let selections = self.cur_buffer().selection.selections.len();
+        Some(vec![Rope::from_str(&text); selections])
+    }
+
+    /// Pipe the current buffer through the formatter of its file type
+    ///
+    /// On failure the buffer is left as it was, and the error is shown.
+    pub fn format_buffer(&mut self) {
+        let path = self.cur_buffer_state().path.clone();
+        self.format_buffer_as(path.as_deref());
+    }
+
+    /// Like `format_buffer`, with `%` in the command replaced by `path`
+    fn format_buffer_as(&mut self, path: Option<&Path>) {
+        let formatter = match self.cur_buffer().filetype.formatter {
+            Some(formatter) => formatter,
+            None => {
+                self.msg = Some(format!(
+                    "No formatter for {} files",
+                    self.cur_buffer().filetype.name
+                ));
+                return;
+            }
+        };
+        let path = path.map(|p| p.display().to_string()).unwrap_or_default();
+        let cmd: Vec<String> = formatter
+            .iter()
+            .map(|&arg| {
+                if arg == "%" {
+                    path.clone()
+                } else {
+                    arg.to_owned()
+                }
+            })
+            .collect();
+
+        match (self.format_handler)(&cmd, &self.cur_buffer().text) {
+            Ok(text) => {
+                let buffer_state = self.cur_buffer_state_mut();
+                buffer_state.maybe_commit_undo_point();
+                buffer_state.buffer.replace_text(&text);
+                buffer_state.maybe_commit_undo_point();
+            }
+            Err(e) => self.msg = Some(format!("{}: {}", cmd[0], e)),
+        }
     }
 
     pub fn write_buffer(&mut self, path: Option<PathBuf>) {
         if let Some(path) = path.or_else(|| self.cur_buffer_state().path.clone()) {
-            match self.try_write_buffer(&path) {
+            if self.format_on_save && self.cur_buffer().filetype.formatter.is_some() {
+                self.format_buffer_as(Some(&path));
+            }
+            let props = self.editorconfig_for(&path);
+            // the buffer only gets these edits once they are written
+            let mut edited = match self.cur_buffer_opt() {
+                Some(buffer) => buffer.clone(),
+                None => return,
+            };
+            if props.trim_trailing_whitespace == Some(true) {
+                edited.trim_trailing_whitespace();
+            }
+            if let Some(final_newline) = props.insert_final_newline {
+                edited.set_final_newline(final_newline);
+            }
+
+            match (self.write_handler)(&path, &props.encode(&edited.text)) {
                 Ok(()) => {
-                    self.cur_buffer_state_mut().path = Some(path);
+                    let buffer_state = self.cur_buffer_state_mut();
+                    if edited.text != buffer_state.buffer.text {
+                        buffer_state.maybe_commit_undo_point();
+                        buffer_state.buffer = edited;
+                        buffer_state.maybe_commit_undo_point();
+                    }
+                    buffer_state.path = Some(path);
+                    buffer_state.saved_text = buffer_state.buffer.text.clone();
+                    if let Some(charset) = props.unsupported_charset() {
+                        self.msg =
+                            Some(format!("Unsupported charset {}, written as UTF-8", charset));
+                    }
                 }
                 Err(e) => {
                     self.msg = Some(format!("{}", e));
@@ -181,18 +535,25 @@ impl State {
         }
     }
 
-    fn try_write_buffer(&self, path: &Path) -> io::Result<()> {
-        if let Some(cur_buffer_i) = self.cur_buffer_i {
-            (self.write_handler)(path, &self.buffers[cur_buffer_i].buffer.text)?;
-        }
-
-        Ok(())
-    }
-
     pub fn delete_buffer(&mut self) {
         if let Some(cur_buffer_i) = self.cur_buffer_i {
             self.buffers.remove(cur_buffer_i);
-            self.buffer_next()
+            match self.alt_buffer_i {
+                Some(alt_buffer_i) if self.buffers.contains(alt_buffer_i) => {
+                    self.cur_buffer_i = Some(alt_buffer_i);
+                }
+                _ => self.buffer_next(),
+            }
+            self.alt_buffer_i = None;
+
+            for (_, window) in self.windows.iter_mut() {
+                if window.buffer_i == Some(cur_buffer_i) {
+                    window.buffer_i = self.cur_buffer_i;
+                }
+                if window.alt_buffer_i == Some(cur_buffer_i) {
+                    window.alt_buffer_i = None;
+                }
+            }
         }
     }
 
@@ -203,7 +564,7 @@ impl State {
                 cur_buffer_i += 1;
                 cur_buffer_i %= self.buffers.capacity();
                 if self.buffers.contains(cur_buffer_i) {
-                    self.cur_buffer_i = Some(cur_buffer_i);
+                    self.set_cur_buffer(cur_buffer_i);
                     break;
                 }
             }
@@ -222,7 +583,7 @@ impl State {
                     cur_buffer_i -= 1;
                 }
                 if self.buffers.contains(cur_buffer_i) {
-                    self.cur_buffer_i = Some(cur_buffer_i);
+                    self.set_cur_buffer(cur_buffer_i);
                     break;
                 }
             }
@@ -231,6 +592,148 @@ impl State {
         }
     }
 
+    /// Save the state of the focused window in `self.windows`
+    fn save_cur_window(&mut self) {
+        let window = &mut self.windows[self.cur_window_i];
+        window.buffer_i = self.cur_buffer_i;
+        window.alt_buffer_i = self.alt_buffer_i;
+        if let Some(buffer_i) = self.cur_buffer_i {
+            let buffer = &mut self.buffers[buffer_i].buffer;
+            let selection = buffer.selection.clone();
+            buffer
+                .window_selections
+                .insert(self.cur_window_i, selection);
+            window.view_line_offset = *buffer.view_line_offset.borrow();
+        }
+    }
+
+    /// Make `window_i` the focused window, without saving the current one
+    fn load_window(&mut self, window_i: usize) {
+        self.cur_window_i = window_i;
+        let window = &self.windows[window_i];
+        self.cur_buffer_i = window.buffer_i;
+        self.alt_buffer_i = window.alt_buffer_i;
+        if let Some(buffer_i) = window.buffer_i {
+            let buffer = &mut self.buffers[buffer_i].buffer;
+            buffer.selection = buffer
+                .window_selections
+                .remove(&window_i)
+                .unwrap_or_default();
+            // undo doesn't move them, like the edits do
+            buffer.selection.trim_to_text(&buffer.text);
+            *buffer.view_line_offset.borrow_mut() = window.view_line_offset;
+        }
+    }
+
+    pub fn focus_window(&mut self, window_i: usize) {
+        if window_i != self.cur_window_i && self.windows.contains(window_i) {
+            self.save_cur_window();
+            self.load_window(window_i);
+        }
+    }
+
+    pub fn focus_window_in_direction(&mut self, direction: Direction) {
+        let found = window::window_in_direction(
+            &self.last_window_rects.borrow(),
+            self.cur_window_i,
+            direction,
+        );
+        if let Some(window_i) = found {
+            self.focus_window(window_i);
+        }
+    }
+
+    pub fn focus_window_next(&mut self) {
+        let windows = self.layout.windows();
+        if let Some(pos) = windows.iter().position(|&i| i == self.cur_window_i) {
+            self.focus_window(windows[(pos + 1) % windows.len()]);
+        }
+    }
+
+    /// Split the focused window in two, and focus the new one
+    pub fn split_window(&mut self, direction: SplitDirection) {
+        self.save_cur_window();
+        let new_window_i = self.windows.insert(self.windows[self.cur_window_i].clone());
+        self.layout
+            .split(self.cur_window_i, new_window_i, direction);
+        self.cur_window_i = new_window_i;
+    }
+
+    pub fn close_window(&mut self) {
+        if self.layout.remove(self.cur_window_i) {
+            self.windows.remove(self.cur_window_i);
+            self.forget_window(self.cur_window_i);
+            self.load_window(self.layout.first_window());
+        } else {
+            self.msg = Some("Can't close the last window".into());
+        }
+    }
+
+    /// Close all the windows except the focused one
+    pub fn only_window(&mut self) {
+        for window_i in self.layout.windows() {
+            if window_i != self.cur_window_i {
+                self.windows.remove(window_i);
+                self.forget_window(window_i);
+            }
+        }
+        self.layout = Layout::Window(self.cur_window_i);
+    }
+
+    /// A new `Context`, with a window on the current buffer
+    pub fn new_context(&mut self) -> Context {
+        self.save_cur_window();
+        let window = self.windows[self.cur_window_i].clone();
+        let window_i = self.windows.insert(window.clone());
+        // starting where the current one is
+        if let Some(buffer_i) = window.buffer_i {
+            let buffer = &mut self.buffers[buffer_i].buffer;
+            let selection = buffer.selection.clone();
+            buffer.window_selections.insert(window_i, selection);
+        }
+        Context {
+            mode: Some(Box::new(mode::Normal)),
+            layout: Layout::Window(window_i),
+            cur_window_i: window_i,
+            last_window_rects: RefCell::new(vec![]),
+            num_prefix: None,
+            recording_edit: None,
+            msg: None,
+        }
+    }
+
+    /// Switch to the `context`, putting the current one in its place
+    pub fn swap_context(&mut self, context: &mut Context) {
+        self.save_cur_window();
+        std::mem::swap(&mut self.mode, &mut context.mode);
+        std::mem::swap(&mut self.layout, &mut context.layout);
+        std::mem::swap(&mut self.cur_window_i, &mut context.cur_window_i);
+        std::mem::swap(&mut self.last_window_rects, &mut context.last_window_rects);
+        std::mem::swap(&mut self.num_prefix, &mut context.num_prefix);
+        std::mem::swap(&mut self.recording_edit, &mut context.recording_edit);
+        std::mem::swap(&mut self.msg, &mut context.msg);
+        self.load_window(self.cur_window_i);
+    }
+
+    /// Close the windows of a `context` that is not the current one
+    pub fn drop_context(&mut self, context: Context) {
+        for window_i in context.layout.windows() {
+            self.windows.remove(window_i);
+            self.forget_window(window_i);
+        }
+    }
+
+    /// Drop the selections of a closed window
+    fn forget_window(&mut self, window_i: usize) {
+        for (_, buffer_state) in self.buffers.iter_mut() {
+            buffer_state.buffer.window_selections.remove(&window_i);
+        }
+    }
+
+    pub fn resize_window(&mut self, direction: SplitDirection, delta: isize) {
+        self.layout.resize(self.cur_window_i, direction, delta);
+    }
+
     pub fn is_finished(&self) -> bool {
         self.quit
     }
@@ -244,6 +747,13 @@ impl State {
         if self.mode.is_none() {
             self.mode = Some(mode)
         }
+
+        for key in std::mem::take(&mut self.pending_keys) {
+            if self.is_finished() {
+                break;
+            }
+            self.handle_key(key);
+        }
     }
 
     pub fn cur_buffer_opt(&self) -> Option<&Buffer> {
@@ -282,6 +792,56 @@ impl State {
         self.mode.as_ref().expect("mode set").name()
     }
 
+    /// Did the current key switch to the insert mode, that will keep
+    /// recording the edit until it's done
+    pub(crate) fn entering_insert(&self) -> bool {
+        // set only if the key switched the mode, see `handle_key`
+        match &self.mode {
+            Some(mode) => mode.records_edit(),
+            None => false,
+        }
+    }
+
+    /// Repeat the last edit, with `num_prefix` if given, or the original one
+    pub(crate) fn repeat_last_edit(&mut self) {
+        if let Some(edit) = self.last_edit.clone() {
+            let num_prefix = self.num_prefix.or(edit.num_prefix);
+            self.pending_keys = num_prefix
+                .map(|n| n.to_string().chars().map(Key::Char).collect())
+                .unwrap_or_default();
+            self.pending_keys.extend(edit.keys);
+        } else {
+            self.msg = Some("Nothing to repeat".into());
+        }
+    }
+
+    /// Set an editor option, as with `:set name value`
+    pub(crate) fn set_option(&mut self, name: &str, value: Option<&str>) {
+        match name {
+            "autopairs" => match value.map(parse_bool_option) {
+                Some(Some(value)) => self.auto_pairs = value,
+                Some(None) => self.msg = Some(format!("invalid value for {}", name)),
+                None => self.msg = Some(format!("autopairs={}", self.auto_pairs)),
+            },
+            "expandtabs" => match value.map(parse_bool_option) {
+                Some(Some(value)) => self.cur_buffer_mut().expand_tabs = value,
+                Some(None) => self.msg = Some(format!("invalid value for {}", name)),
+                None => self.msg = Some(format!("expandtabs={}", self.cur_buffer().expand_tabs)),
+            },
+            "tabstop" => match value.map(str::parse) {
+                Some(Ok(value)) if value > 0 => self.cur_buffer_mut().tabstop = value,
+                Some(_) => self.msg = Some(format!("invalid value for {}", name)),
+                None => self.msg = Some(format!("tabstop={}", self.cur_buffer().tabstop)),
+            },
+            "formatonsave" => match value.map(parse_bool_option) {
+                Some(Some(value)) => self.format_on_save = value,
+                Some(None) => self.msg = Some(format!("invalid value for {}", name)),
+                None => self.msg = Some(format!("formatonsave={}", self.format_on_save)),
+            },
+            _ => self.msg = Some(format!("unknown option: {}", name)),
+        }
+    }
+
     pub fn register_read_handler(&mut self, f: impl Fn(&Path) -> io::Result<Rope> + 'static) {
         self.read_handler = Arc::new(f);
     }
@@ -292,22 +852,132 @@ impl State {
 
     pub fn register_find_handler(
         &mut self,
-        f: impl Fn(&str) -> io::Result<Vec<PathBuf>> + 'static,
+        f: impl Fn(usize) -> io::Result<Vec<PathBuf>> + 'static,
     ) {
         self.find_handler = Arc::new(f);
     }
 
+    pub fn register_format_handler(
+        &mut self,
+        f: impl Fn(&[String], &Rope) -> io::Result<Rope> + 'static,
+    ) {
+        self.format_handler = Arc::new(f);
+    }
+
+    pub fn register_clipboard_handler(
+        &mut self,
+        copy: impl Fn(&str) -> io::Result<()> + 'static,
+        paste: impl Fn() -> io::Result<String> + 'static,
+    ) {
+        self.clipboard_copy_handler = Arc::new(copy);
+        self.clipboard_paste_handler = Arc::new(paste);
+    }
+
+    pub fn load_plugin(
+        &mut self,
+        name: &str,
+        wasm: &[u8],
+        capabilities: &[Capability],
+        limits: plugin::Limits,
+    ) -> crate::prelude::Result<()> {
+        self.plugins.load(name, wasm, capabilities, limits)
+    }
+
+    /// Run an action registered by a plugin; `false` if there's no such action
+    pub fn run_plugin_action(&mut self, name: &str) -> bool {
+        let buffer = self.cur_buffer_opt().cloned();
+        match self.plugins.run_action(name, buffer) {
+            Some(res) => {
+                self.apply_plugin_call(res);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Run a command registered by a plugin; `false` if there's no such command
+    pub fn run_plugin_command(&mut self, name: &str, args: &str) -> bool {
+        let buffer = self.cur_buffer_opt().cloned();
+        match self.plugins.run_command(name, args, buffer) {
+            Some(res) => {
+                self.apply_plugin_call(res);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn apply_plugin_call(&mut self, res: crate::prelude::Result<plugin::CallOutput>) {
+        match res {
+            Ok(output) => {
+                if let (Some(buffer), Some(cur_buffer)) = (output.buffer, self.cur_buffer_mut_opt())
+                {
+                    *cur_buffer = buffer;
+                    cur_buffer.selection.trim_to_text(&cur_buffer.text);
+                }
+                if output.msg.is_some() {
+                    self.msg = output.msg;
+                }
+            }
+            Err(e) => self.msg = Some(e.to_string()),
+        }
+    }
+
     pub fn render(&self, render: &mut dyn Renderer) {
         self.mode.as_ref().expect("mode set").render(self, render);
     }
 
+    /// Render all the windows
     pub fn render_buffer(&self, mut render: &mut dyn Renderer) {
-        if self.cur_buffer_opt().is_none() {
-            self.render_splash(render);
-            return;
+        let mut window_rects = vec![];
+        let mut separators = vec![];
+        self.layout
+            .window_rects(render.dimensions_rect(), &mut window_rects, &mut separators);
+
+        let separator_style = render.color_map().line_num;
+        for (direction, rect) in separators {
+            let ch = match direction {
+                SplitDirection::Horizontal => '─',
+                SplitDirection::Vertical => '│',
+            };
+            render.fill(rect, ch, separator_style);
+        }
+
+        for &(window_i, rect) in &window_rects {
+            let mut view = rect.to_renderer(&mut render);
+            if window_i == self.cur_window_i {
+                if let Some(buffer) = self.cur_buffer_opt() {
+                    self.render_window(buffer, true, &mut view);
+                    // make it relative to the whole area, not just this window
+                    if let Some(coord) = self.last_visual_cursor_coord.borrow_mut().as_mut() {
+                        *coord = *coord + rect.offset;
+                    }
+                } else {
+                    self.render_splash(&mut view);
+                }
+            } else {
+                let window = &self.windows[window_i];
+                if let Some(buffer_i) = window.buffer_i {
+                    let mut buffer = self.buffers[buffer_i].buffer.clone();
+                    buffer.selection = buffer
+                        .window_selections
+                        .get(&window_i)
+                        .cloned()
+                        .unwrap_or_default();
+                    buffer.selection.trim_to_text(&buffer.text);
+                    *buffer.view_line_offset.borrow_mut() = window.view_line_offset;
+                    self.render_window(&buffer, false, &mut view);
+                }
+            }
         }
 
-        let buffer = self.cur_buffer();
+        *self.last_window_rects.borrow_mut() = window_rects;
+    }
+
+    /// Render `buffer` in a single window
+    ///
+    /// Only the `focused` one sets the cursor.
+    fn render_window(&self, buffer: &Buffer, focused: bool, mut render: &mut dyn Renderer) {
         let dims = render.dimensions();
 
         let window_height = dims.y;
@@ -334,6 +1004,11 @@ impl State {
 
         drop(line_offset);
         let line_nums_width = buffer.lines().to_string().len() + 1;
+        if dims.x <= line_nums_width + 1 {
+            // too narrow for line numbers
+            self.render_content(buffer, focused, render, start_line);
+            return;
+        }
         let (line_nums_rect, content_rect) = render
             .dimensions_rect()
             .split_verticaly_at(line_nums_width as isize);
@@ -342,7 +1017,12 @@ impl State {
             start_line,
             end_line,
         );
-        self.render_content(&mut content_rect.to_renderer(&mut render), start_line);
+        self.render_content(
+            buffer,
+            focused,
+            &mut content_rect.to_renderer(&mut render),
+            start_line,
+        );
     }
 
     pub fn render_line_nums(&self, render: &mut dyn Renderer, start_line: usize, end_line: usize) {
@@ -361,12 +1041,22 @@ impl State {
         }
     }
 
-    pub fn render_content(&self, render: &mut dyn Renderer, start_line: usize) {
-        let buffer = self.cur_buffer();
+    pub fn render_content(
+        &self,
+        buffer: &Buffer,
+        focused: bool,
+        render: &mut dyn Renderer,
+        start_line: usize,
+    ) {
         let window_dims = render.dimensions();
         let cursor_coord = buffer.cursor_coord();
 
         let color_map = { render.color_map().to_owned() };
+        let bracket_pair = if focused {
+            buffer.matching_bracket_pair()
+        } else {
+            None
+        };
 
         let mut cur_ch_idx = position::Position {
             line: start_line,
@@ -423,6 +1113,13 @@ impl State {
                 VisualSelection::None => style,
             };
 
+            let style = match bracket_pair {
+                Some((a, b)) if a.0 == cur_ch_idx || b.0 == cur_ch_idx => {
+                    style.paintover(color_map.matching_bracket)
+                }
+                _ => style,
+            };
+
             if ch == '\n' {
                 if let Some(visual_ch) = visual_ch {
                     render.put(cur_visual_coord, visual_ch, style);
@@ -440,12 +1137,14 @@ impl State {
             cur_ch_idx += 1;
         }
 
-        let visual_cursor_coord = render::Coord {
-            y: cursor_coord.line.saturating_sub(start_line),
-            x: cursor_coord.column,
-        };
-        *self.last_visual_cursor_coord.borrow_mut() = Some(visual_cursor_coord);
-        render.set_cursor(Some(visual_cursor_coord));
+        if focused {
+            let visual_cursor_coord = render::Coord {
+                y: cursor_coord.line.saturating_sub(start_line),
+                x: cursor_coord.column,
+            };
+            *self.last_visual_cursor_coord.borrow_mut() = Some(visual_cursor_coord);
+            render.set_cursor(Some(visual_cursor_coord));
+        }
     }
 
     pub fn render_splash(&self, render: &mut dyn Renderer) {
@@ -469,19 +1168,31 @@ impl Default for BufferState {
             buffer_history: vec![],
             buffer_history_undo_i: None,
             path: None,
+            saved_text: Rope::new(),
         }
     }
 }
 impl Default for State {
     fn default() -> Self {
+        let mut windows = Slab::new();
+        let cur_window_i = windows.insert(Window::default());
+
         State {
             quit: false,
             mode: Some(Box::new(mode::Normal::default())),
             yanked: vec![],
+            copied: None,
             msg: None,
 
             buffers: Slab::new(),
             cur_buffer_i: None,
+            alt_buffer_i: None,
+            recent_files: vec![],
+
+            windows,
+            layout: Layout::Window(cur_window_i),
+            cur_window_i,
+            last_window_rects: RefCell::new(vec![]),
 
             read_handler: Arc::new(|_path| {
                 Err(io::Error::new(
@@ -495,7 +1206,25 @@ impl Default for State {
                     "handler not registered",
                 ))
             }),
-            find_handler: Arc::new(|_str| {
+            find_handler: Arc::new(|_start| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            format_handler: Arc::new(|_cmd, _rope| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            clipboard_copy_handler: Arc::new(|_text| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "handler not registered",
+                ))
+            }),
+            clipboard_paste_handler: Arc::new(|| {
                 Err(io::Error::new(
                     io::ErrorKind::NotConnected,
                     "handler not registered",
@@ -503,6 +1232,122 @@ impl Default for State {
             }),
             last_visual_cursor_coord: RefCell::new(None),
             num_prefix: None,
+            last_edit: None,
+            last_find: None,
+            recording_edit: None,
+            pending_keys: vec![],
+            auto_pairs: true,
+            format_on_save: false,
+            plugins: default(),
         }
     }
 }
+
+#[test]
+fn buffer_switch_test() {
+    let long: String = (0..30).map(|i| format!("line {}\n", i)).collect();
+    let mut brz = crate::headless::headless_with_files(&[("a.txt", &long), ("b.txt", "bbb\n")]);
+    brz.keys(":e a.txt<ret>:e b.txt<ret>");
+    assert_eq!(brz.text(), "bbb\n");
+
+    // Ctrl-^ toggles between the last two
+    brz.keys("<c-^>");
+    assert_eq!(brz.text(), long);
+    brz.keys("<c-^>");
+    assert_eq!(brz.text(), "bbb\n");
+
+    brz.keys(":b 1<ret>");
+    assert_eq!(brz.text(), long);
+    brz.keys("20jix<esc>");
+    brz.snapshot();
+    let selection = brz.state.cur_buffer().selection.clone();
+    let view_line_offset = *brz.state.cur_buffer().view_line_offset.borrow();
+    assert!(view_line_offset > 0);
+
+    brz.keys(":ls<ret>");
+    assert_eq!(
+        brz.state.msg.as_deref(),
+        Some("  1 % a.txt [+]\n  2 # b.txt")
+    );
+
+    // by a part of the name, and back
+    brz.keys(":b b.t<ret>");
+    assert_eq!(brz.text(), "bbb\n");
+    brz.snapshot();
+    brz.keys(":b a<ret>");
+    assert_eq!(brz.state.cur_buffer().selection, selection);
+    assert_eq!(
+        *brz.state.cur_buffer().view_line_offset.borrow(),
+        view_line_offset
+    );
+    assert!(brz.snapshot().contains("xline 20"));
+
+    brz.keys(":w<ret>:ls<ret>");
+    assert_eq!(brz.state.msg.as_deref(), Some("  1 % a.txt\n  2 # b.txt"));
+    assert!(brz.file("a.txt").unwrap().contains("xline 20\n"));
+
+    brz.keys(":new<ret>itmp<esc>:ls<ret>");
+    assert_eq!(brz.text(), "tmp");
+    assert_eq!(
+        brz.state.msg.as_deref(),
+        Some("  1 # a.txt\n  2   b.txt\n  3 % *scratch* [+]")
+    );
+    brz.keys(":b 4<ret>");
+    assert_eq!(brz.state.msg.as_deref(), Some("No buffer number 4"));
+}
+
+#[test]
+fn format_test() {
+    let mut brz = crate::headless::headless_with_files(&[("a.rs", "fn  f() {\nx;\n}\n")]);
+    brz.state.register_format_handler(|cmd, rope| {
+        assert_eq!(cmd[0], "rustfmt");
+        let text = rope.to_string();
+        if text.contains("error") {
+            return Err(std::io::Error::other("bad syntax"));
+        }
+        Ok(ropey::Rope::from_str(
+            &text.replace("fn  f", "fn f").replace("\nx;", "\n    x;"),
+        ))
+    });
+    brz.state.open_buffer(Path::new("a.rs"));
+
+    // cursor right after `x`
+    brz.select(&[(10, 11)]);
+    brz.keys(":format<ret>");
+    assert_eq!(brz.text(), "fn f() {\n    x;\n}\n");
+    assert_eq!(
+        brz.state.cur_buffer().selection.selections[0].cursor,
+        Idx(14)
+    );
+
+    brz.keys("u");
+    assert_eq!(brz.text(), "fn  f() {\nx;\n}\n");
+
+    // failures don't touch the buffer
+    brz.keys("ierror <esc>:format<ret>");
+    assert_eq!(brz.text(), "fn  f() {\nxerror ;\n}\n");
+    assert!(brz.snapshot().contains("rustfmt: bad syntax"));
+
+    brz.keys("u:set formatonsave on<ret>:w<ret>");
+    assert_eq!(brz.text(), "fn f() {\n    x;\n}\n");
+}
+
+#[test]
+fn clipboard_test() {
+    let mut brz = crate::headless::headless_with_text("one two\n");
+
+    brz.select(&[(0, 3), (4, 7)]);
+    brz.keys("<a-y>");
+    assert_eq!(brz.clipboard(), "one\ntwo");
+
+    // every selection gets its own part back, like with `p`
+    brz.keys("<a-p>");
+    assert_eq!(brz.text(), "oneone twotwo\n");
+    brz.keys("up");
+    assert_eq!(brz.text(), "oneone twotwo\n");
+
+    brz.keys("u");
+    brz.set_clipboard("x");
+    brz.keys("<a-p>");
+    assert_eq!(brz.text(), "onex twox\n");
+}
@@ -0,0 +1,101 @@
+//! Finding symbols (definitions) in the text
+//!
+//! There is no real parsing here, just a line based heuristic that works
+//! well enough for most C-like languages and Python.
+
+use crate::position::Position;
+use ropey::Rope;
+
+const DEFINITION_KEYWORDS: &[&str] = &[
+    "fn",
+    "struct",
+    "enum",
+    "union",
+    "trait",
+    "impl",
+    "mod",
+    "type",
+    "macro_rules!",
+    "def",
+    "class",
+    "function",
+    "func",
+    "interface",
+];
+
+const DEFINITION_MODIFIERS: &[&str] = &[
+    "pub", "async", "unsafe", "extern", "const", "default", "export", "static",
+];
+
+fn is_definition_modifier(word: &str) -> bool {
+    DEFINITION_MODIFIERS.contains(&word) || word.starts_with("pub(") || word.starts_with('"')
+}
+
+/// If `line` starts a definition, return its label
+fn line_definition(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let keyword = line
+        .split_whitespace()
+        .find(|word| !is_definition_modifier(word))?;
+
+    if !DEFINITION_KEYWORDS.contains(&keyword) {
+        return None;
+    }
+
+    Some(
+        line.trim_end_matches(|ch: char| ch == '{' || ch == ':' || ch.is_whitespace())
+            .trim_end(),
+    )
+}
+
+/// Find all the symbols in the `text`
+///
+/// Returns label of each symbol, along with a position
+/// of the first non-whitespace character in its line.
+pub fn find_symbols(text: &Rope) -> Vec<(Position, String)> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(line_i, line)| {
+            let line = line.to_string();
+            line_definition(&line).map(|label| {
+                let column = line.chars().take_while(|ch| ch.is_whitespace()).count();
+                (
+                    Position {
+                        line: line_i,
+                        column,
+                    },
+                    label.to_owned(),
+                )
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn find_symbols_test() {
+    let text = Rope::from_str(
+        "use foo;\n\npub(crate) struct Foo {\n    a: u32,\n}\n\nimpl Foo {\n    pub async fn bar(&self) {\n        let fn_ = 1;\n    }\n}\ndef baz():\n",
+    );
+
+    assert_eq!(
+        find_symbols(&text),
+        vec![
+            (
+                Position { line: 2, column: 0 },
+                "pub(crate) struct Foo".to_owned()
+            ),
+            (Position { line: 6, column: 0 }, "impl Foo".to_owned()),
+            (
+                Position { line: 7, column: 4 },
+                "pub async fn bar(&self)".to_owned()
+            ),
+            (
+                Position {
+                    line: 11,
+                    column: 0
+                },
+                "def baz()".to_owned()
+            ),
+        ]
+    );
+}
@@ -0,0 +1,528 @@
+//! Text objects
+//!
+//! Parts of the text around a selection, like a word, a paragraph or
+//! the inside of parentheses, that the object mode can select.
+
+use crate::idx::Idx;
+use crate::selection::Selection;
+use crate::util::char;
+use ropey::Rope;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextObject {
+    /// Word forming chars (or a run of any other chars of one kind)
+    Word,
+    /// Non-whitespace chars
+    BigWord,
+    Sentence,
+    Paragraph,
+    /// Surrounded by the given opening and closing chars
+    Surrounded(char, char),
+    /// Quoted with the given char, on a single line
+    Quoted(char),
+    /// Function argument, delimited by commas and brackets
+    Argument,
+    /// Lines indented at least as much as the current one
+    IndentBlock,
+    Number,
+}
+
+impl TextObject {
+    /// Object for a key pressed in the object mode
+    pub fn from_key_char(ch: char) -> Option<Self> {
+        use self::TextObject::*;
+        Some(match ch {
+            'w' => Word,
+            'W' => BigWord,
+            's' => Sentence,
+            'p' => Paragraph,
+            'b' | '(' | ')' => Surrounded('(', ')'),
+            'B' | '{' | '}' => Surrounded('{', '}'),
+            'r' | '[' | ']' => Surrounded('[', ']'),
+            'a' | '<' | '>' => Surrounded('<', '>'),
+            'Q' | '"' => Quoted('"'),
+            'q' | '\'' => Quoted('\''),
+            'g' | '`' => Quoted('`'),
+            'u' => Argument,
+            'i' => IndentBlock,
+            'n' => Number,
+            _ => return None,
+        })
+    }
+
+    /// Find the object at `sel`
+    ///
+    /// Returns the sorted `(start, end)` of the object; `around` includes
+    /// the delimiters or the whitespace after it.
+    pub fn find(self, sel: Selection, around: bool, text: &Rope) -> Option<(Idx, Idx)> {
+        if text.len_chars() == 0 {
+            return None;
+        }
+
+        let sel = sel.normalized(text);
+        let (left, right) = sel.sorted_pair();
+        // the char under the direction marker
+        let at = if sel.is_forward() {
+            sel.cursor.backward(text)
+        } else {
+            sel.cursor
+        };
+
+        match self {
+            TextObject::Word => Some(find_word(at, around, char_class, text)),
+            TextObject::BigWord => Some(find_word(at, around, big_char_class, text)),
+            TextObject::Sentence => Some(find_sentence(at, around, text)),
+            TextObject::Paragraph => Some(find_paragraph(at, around, text)),
+            TextObject::Surrounded(open, close) => {
+                find_surrounded(left, right, open, close, around, text)
+            }
+            TextObject::Quoted(quote) => find_quoted(at, quote, around, text),
+            TextObject::Argument => find_argument(at, around, text),
+            TextObject::IndentBlock => Some(find_indent_block(at, around, text)),
+            TextObject::Number => find_number(at, around, text),
+        }
+    }
+}
+
+fn char_class(ch: char) -> u8 {
+    if char::is_word_forming(ch) {
+        0
+    } else if ch.is_whitespace() {
+        1
+    } else {
+        2
+    }
+}
+
+fn big_char_class(ch: char) -> u8 {
+    if ch.is_whitespace() {
+        1
+    } else {
+        0
+    }
+}
+
+fn find_word(at: Idx, around: bool, class: fn(char) -> u8, text: &Rope) -> (Idx, Idx) {
+    let at_class = class(text.char(at.0));
+    let mut start = at.backward_while(|ch| ch != '\n' && class(ch) == at_class, text);
+    let mut end = at.forward_while(|ch| ch != '\n' && class(ch) == at_class, text);
+
+    if around {
+        let end_with_space = end.forward_while(char::is_non_newline_whitespace, text);
+        if end_with_space != end {
+            end = end_with_space;
+        } else {
+            start = start.backward_while(char::is_non_newline_whitespace, text);
+        }
+    }
+    (start, end)
+}
+
+fn is_sentence_end(ch: char) -> bool {
+    ch == '.' || ch == '!' || ch == '?'
+}
+
+/// Is there an empty line right before `idx`
+fn is_after_empty_line(idx: Idx, text: &Rope) -> bool {
+    idx.prev_char(text) == Some('\n') && idx.backward(text).prev_char(text) == Some('\n')
+}
+
+fn find_sentence(at: Idx, around: bool, text: &Rope) -> (Idx, Idx) {
+    let mut start = at;
+    while let Some(ch) = start.prev_char(text) {
+        let before = start.backward(text);
+        if ch.is_whitespace()
+            && matches!(before.prev_char(text), Some(prev) if is_sentence_end(prev))
+        {
+            break;
+        }
+        if is_after_empty_line(start, text) {
+            break;
+        }
+        start = before;
+    }
+    let start = start.forward_while(char::is_whitespace, text);
+
+    let mut end = at;
+    while let Some(ch) = end.next_char(text) {
+        end = end.forward(text);
+        if is_sentence_end(ch) && end.next_char(text).map(char::is_whitespace).unwrap_or(true) {
+            break;
+        }
+        if ch == '\n' && end.next_char(text) == Some('\n') {
+            break;
+        }
+    }
+
+    let end = if around {
+        end.forward_while(char::is_non_newline_whitespace, text)
+    } else {
+        end
+    };
+    (start, end)
+}
+
+fn is_blank_line(idx: Idx, text: &Rope) -> bool {
+    let line_end = idx.forward_to_line_end(text);
+    idx.backward_to_line_start(text)
+        .forward_while(char::is_non_newline_whitespace, text)
+        == line_end
+}
+
+/// Extend from the line of `idx`, by whole lines, while `f` holds for them
+fn lines_while(idx: Idx, mut f: impl FnMut(Idx) -> bool, text: &Rope) -> (Idx, Idx) {
+    let mut start = idx.backward_to_line_start(text);
+    while start.0 > 0 {
+        let prev_line = start.backward(text).backward_to_line_start(text);
+        if !f(prev_line) {
+            break;
+        }
+        start = prev_line;
+    }
+
+    let mut end = idx.forward_past_line_end(text);
+    while end < Idx::end(text) && f(end) {
+        end = end.forward_past_line_end(text);
+    }
+    (start, end)
+}
+
+fn find_paragraph(at: Idx, around: bool, text: &Rope) -> (Idx, Idx) {
+    let blank = is_blank_line(at, text);
+    let (start, end) = lines_while(at, |line| is_blank_line(line, text) == blank, text);
+
+    if around && !blank && end < Idx::end(text) {
+        let (_, end) = lines_while(end, |line| is_blank_line(line, text), text);
+        (start, end)
+    } else {
+        (start, end)
+    }
+}
+
+/// Index of the `open` char that is not closed before `idx`
+fn backward_to_unmatched(idx: Idx, open: char, close: char, text: &Rope) -> Option<Idx> {
+    let mut depth = 0;
+    let after_open = idx.backward_while(
+        |ch| {
+            if ch == close {
+                depth += 1;
+            } else if ch == open {
+                if depth == 0 {
+                    return false;
+                }
+                depth -= 1;
+            }
+            true
+        },
+        text,
+    );
+    if after_open.prev_char(text) == Some(open) {
+        Some(after_open.backward(text))
+    } else {
+        None
+    }
+}
+
+/// Index of the `close` char, that is not opened after `idx`
+fn forward_to_unmatched(idx: Idx, open: char, close: char, text: &Rope) -> Option<Idx> {
+    let mut depth = 0;
+    let close_idx = idx.forward_while(
+        |ch| {
+            if ch == open {
+                depth += 1;
+            } else if ch == close {
+                if depth == 0 {
+                    return false;
+                }
+                depth -= 1;
+            }
+            true
+        },
+        text,
+    );
+    if close_idx.next_char(text) == Some(close) {
+        Some(close_idx)
+    } else {
+        None
+    }
+}
+
+fn find_surrounded(
+    left: Idx,
+    right: Idx,
+    open: char,
+    close: char,
+    around: bool,
+    text: &Rope,
+) -> Option<(Idx, Idx)> {
+    // selection starting at `open`, or ending at `close` is already
+    // inside the pair
+    let left = if left.next_char(text) == Some(open) {
+        left.forward(text)
+    } else {
+        left
+    };
+    let right = if right.prev_char(text) == Some(close) {
+        right.backward(text)
+    } else {
+        right
+    };
+
+    let open_idx = backward_to_unmatched(left, open, close, text)?;
+    let close_idx = forward_to_unmatched(right, open, close, text)?;
+    Some(if around {
+        (open_idx, close_idx.forward(text))
+    } else {
+        (open_idx.forward(text), close_idx)
+    })
+}
+
+fn find_quoted(at: Idx, quote: char, around: bool, text: &Rope) -> Option<(Idx, Idx)> {
+    let line_start = at.backward_to_line_start(text);
+    let line_end = at.forward_to_line_end(text);
+
+    let mut quotes = vec![];
+    let mut escaped = false;
+    for (i, ch) in text.slice(line_start.0..line_end.0).chars().enumerate() {
+        if ch == quote && !escaped {
+            quotes.push(Idx(line_start.0 + i));
+        }
+        escaped = ch == '\\' && !escaped;
+    }
+
+    let pair = quotes
+        .chunks(2)
+        .find(|pair| pair.len() == 2 && pair[0] <= at && at <= pair[1])?;
+    Some(if around {
+        (pair[0], pair[1].forward(text))
+    } else {
+        (pair[0].forward(text), pair[1])
+    })
+}
+
+fn find_argument(at: Idx, around: bool, text: &Rope) -> Option<(Idx, Idx)> {
+    let (inner_start, inner_end) = Idx::find_surounding_area_opt(at, at, text)?;
+
+    // split by the commas that are not nested in any brackets
+    let mut delimiters = vec![];
+    let mut depth = 0usize;
+    for (i, ch) in text.slice(inner_start.0..inner_end.0).chars().enumerate() {
+        if char::is_opening_indent(ch) {
+            depth += 1;
+        } else if char::is_closing_indent(ch) {
+            depth = depth.saturating_sub(1);
+        } else if ch == ',' && depth == 0 {
+            delimiters.push(Idx(inner_start.0 + i));
+        }
+    }
+
+    let prev_comma = delimiters.iter().rev().find(|comma| **comma < at).cloned();
+    let next_comma = delimiters.iter().find(|comma| at <= **comma).cloned();
+
+    let start = prev_comma
+        .map(|comma| comma.forward(text))
+        .unwrap_or(inner_start);
+    let end = next_comma.unwrap_or(inner_end);
+
+    if !around {
+        let start = start.forward_while(char::is_whitespace, text);
+        let end = end.backward_while(char::is_whitespace, text);
+        return Some((start, std::cmp::max(start, end)));
+    }
+
+    Some(match (prev_comma, next_comma) {
+        (_, Some(next_comma)) => (
+            start.forward_while(char::is_whitespace, text),
+            next_comma
+                .forward(text)
+                .forward_while(char::is_whitespace, text),
+        ),
+        (Some(prev_comma), None) => (prev_comma, end.backward_while(char::is_whitespace, text)),
+        (None, None) => (start, end),
+    })
+}
+
+fn indent_of(line: Idx, text: &Rope) -> usize {
+    line.forward_while(char::is_non_newline_whitespace, text).0 - line.0
+}
+
+fn find_indent_block(at: Idx, around: bool, text: &Rope) -> (Idx, Idx) {
+    let indent = indent_of(at.backward_to_line_start(text), text);
+    let (mut start, mut end) = lines_while(
+        at,
+        |line| is_blank_line(line, text) || indent <= indent_of(line, text),
+        text,
+    );
+
+    if !around {
+        // blank lines at the edges are not the part of the block
+        while start < at && is_blank_line(start, text) {
+            start = start.forward_past_line_end(text);
+        }
+        while at < end {
+            let last_line = end.backward(text).backward_to_line_start(text);
+            if !is_blank_line(last_line, text) || last_line <= at.backward_to_line_start(text) {
+                break;
+            }
+            end = last_line;
+        }
+    }
+    (start, end)
+}
+
+fn find_number(at: Idx, around: bool, text: &Rope) -> Option<(Idx, Idx)> {
+    let is_number_char = |ch: char| ch.is_ascii_digit() || ch == '.';
+    if !text.char(at.0).is_ascii_digit() {
+        return None;
+    }
+
+    let start = at.backward_while(is_number_char, text);
+    let start = start.forward_while(|ch| ch == '.', text);
+    let end = at.forward_while(is_number_char, text);
+    let end = end.backward_while(|ch| ch == '.', text);
+
+    if around && start.prev_char(text) == Some('-') {
+        Some((start.backward(text), end))
+    } else {
+        Some((start, end))
+    }
+}
+
+#[cfg(test)]
+fn select(text: &str, at: usize, object: TextObject, around: bool) -> Option<String> {
+    let text = Rope::from_str(text);
+    object
+        .find(
+            Selection::new_from_normalized(Idx(at), Idx(at + 1)),
+            around,
+            &text,
+        )
+        .map(|(start, end)| text.slice(start.0..end.0).to_string())
+}
+
+#[cfg(test)]
+fn select_both(text: &str, at: usize, object: TextObject) -> (String, String) {
+    (
+        select(text, at, object, false).expect("inner"),
+        select(text, at, object, true).expect("around"),
+    )
+}
+
+#[test]
+fn words_test() {
+    let text = "foo_bar  baz.qux(1)";
+    assert_eq!(
+        select_both(text, 1, TextObject::Word),
+        ("foo_bar".into(), "foo_bar  ".into())
+    );
+    assert_eq!(
+        select_both(text, 10, TextObject::Word),
+        ("baz".into(), "  baz".into())
+    );
+    assert_eq!(
+        select_both(text, 14, TextObject::BigWord),
+        ("baz.qux(1)".into(), "  baz.qux(1)".into())
+    );
+}
+
+#[test]
+fn sentences_and_paragraphs_test() {
+    let text = "One two. Three four!\nFive.\n\nSix\nseven.\n";
+    assert_eq!(
+        select_both(text, 12, TextObject::Sentence),
+        ("Three four!".into(), "Three four!".into())
+    );
+    assert_eq!(
+        select_both(text, 2, TextObject::Sentence),
+        ("One two.".into(), "One two. ".into())
+    );
+    assert_eq!(
+        select_both(text, 3, TextObject::Paragraph),
+        (
+            "One two. Three four!\nFive.\n".into(),
+            "One two. Three four!\nFive.\n\n".into()
+        )
+    );
+    assert_eq!(
+        select(text, 29, TextObject::Paragraph, true),
+        Some("Six\nseven.\n".into())
+    );
+}
+
+#[test]
+fn surrounded_and_quoted_test() {
+    let text = "f(a, (b), [c]) \"x \\\" y\" 'z'";
+    assert_eq!(
+        select_both(text, 3, TextObject::Surrounded('(', ')')),
+        ("a, (b), [c]".into(), "(a, (b), [c])".into())
+    );
+    assert_eq!(
+        select(text, 6, TextObject::Surrounded('(', ')'), false),
+        Some("b".into())
+    );
+    assert_eq!(
+        select(text, 1, TextObject::Surrounded('(', ')'), false),
+        Some("a, (b), [c]".into())
+    );
+    assert_eq!(
+        select(text, 11, TextObject::Surrounded('[', ']'), true),
+        Some("[c]".into())
+    );
+    assert_eq!(
+        select(text, 0, TextObject::Surrounded('{', '}'), true),
+        None
+    );
+    assert_eq!(
+        select_both(text, 18, TextObject::Quoted('"')),
+        ("x \\\" y".into(), "\"x \\\" y\"".into())
+    );
+    assert_eq!(
+        select(text, 25, TextObject::Quoted('\''), false),
+        Some("z".into())
+    );
+    assert_eq!(select(text, 1, TextObject::Quoted('\''), false), None);
+}
+
+#[test]
+fn arguments_test() {
+    let text = "f(a, g(b, c), d)";
+    assert_eq!(
+        select_both(text, 2, TextObject::Argument),
+        ("a".into(), "a, ".into())
+    );
+    assert_eq!(
+        select_both(text, 5, TextObject::Argument),
+        ("g(b, c)".into(), "g(b, c), ".into())
+    );
+    assert_eq!(
+        select_both(text, 14, TextObject::Argument),
+        ("d".into(), ", d".into())
+    );
+    assert_eq!(
+        select(text, 10, TextObject::Argument, false),
+        Some("c".into())
+    );
+}
+
+#[test]
+fn indent_block_and_number_test() {
+    let text = "fn f() {\n    a;\n\n    if b {\n        c;\n    }\n}\n";
+    assert_eq!(
+        select_both(text, 13, TextObject::IndentBlock),
+        (
+            "    a;\n\n    if b {\n        c;\n    }\n".into(),
+            "    a;\n\n    if b {\n        c;\n    }\n".into()
+        )
+    );
+    assert_eq!(
+        select(text, 36, TextObject::IndentBlock, false),
+        Some("        c;\n".into())
+    );
+
+    let text = "x = -12.5;";
+    assert_eq!(
+        select_both(text, 6, TextObject::Number),
+        ("12.5".into(), "-12.5".into())
+    );
+    assert_eq!(select(text, 0, TextObject::Number, false), None);
+}
@@ -1 +1,4 @@
+pub mod case;
 pub mod char;
+pub mod diff;
+pub mod number;
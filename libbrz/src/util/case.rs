@@ -0,0 +1,203 @@
+//! Letter case and identifier case conversions
+
+pub fn swap_case(s: &str) -> String {
+    s.chars()
+        .flat_map(|ch| -> Box<dyn Iterator<Item = char>> {
+            if ch.is_uppercase() {
+                Box::new(ch.to_lowercase())
+            } else {
+                Box::new(ch.to_uppercase())
+            }
+        })
+        .collect()
+}
+
+/// Case style of an identifier, in the order `cycle_identifier_case` goes through
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IdentifierCase {
+    Snake,
+    Camel,
+    Pascal,
+    Kebab,
+    Screaming,
+}
+
+use self::IdentifierCase::*;
+
+const ALL: [IdentifierCase; 5] = [Snake, Camel, Pascal, Kebab, Screaming];
+
+impl IdentifierCase {
+    pub fn detect(s: &str) -> Self {
+        let has_lower = s.chars().any(char::is_lowercase);
+        let has_upper = s.chars().any(char::is_uppercase);
+        if s.contains('-') {
+            Kebab
+        } else if has_upper && !has_lower {
+            Screaming
+        } else if s.contains('_') || !has_upper {
+            Snake
+        } else if matches!(s.chars().next(), Some(ch) if ch.is_uppercase()) {
+            Pascal
+        } else {
+            Camel
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let i = ALL.iter().position(|&case| case == self).unwrap_or(0);
+        ALL[(i + 1) % ALL.len()]
+    }
+
+    /// Join the `words` of an identifier in this case
+    pub fn join(self, words: &[String]) -> String {
+        let lower: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+
+        match self {
+            Snake => lower.join("_"),
+            Kebab => lower.join("-"),
+            Screaming => words
+                .iter()
+                .map(|w| w.to_uppercase())
+                .collect::<Vec<_>>()
+                .join("_"),
+            Pascal => words
+                .iter()
+                .map(|w| capitalized(w))
+                .collect::<Vec<String>>()
+                .concat(),
+            Camel => lower
+                .iter()
+                .take(1)
+                .cloned()
+                .chain(words.iter().skip(1).map(|w| capitalized(w)))
+                .collect::<Vec<String>>()
+                .concat(),
+        }
+    }
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| {
+            first
+                .to_uppercase()
+                .chain(chars.flat_map(char::to_lowercase))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Split an identifier into words
+///
+/// Words are separated by `_`, `-` (or any other non-alphanumeric char),
+/// and by case changes, eg. `parseHTTPRequest` is `parse`, `HTTP`, `Request`.
+pub fn split_identifier(s: &str) -> Vec<String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut words = vec![];
+    let mut word = String::new();
+
+    for (i, &ch) in chars.iter().enumerate() {
+        if !ch.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if let Some(prev) = word.chars().last() {
+            let next_is_lower = matches!(chars.get(i + 1), Some(ch) if ch.is_lowercase());
+            let boundary = ch.is_uppercase()
+                && (prev.is_lowercase()
+                    || prev.is_numeric()
+                    || (prev.is_uppercase() && next_is_lower));
+            if boundary {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        word.push(ch);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Convert an identifier to the next case style that changes it
+///
+/// Goes `snake_case`, `camelCase`, `PascalCase`, `kebab-case`,
+/// `SCREAMING_CASE`, and back to `snake_case`. Anything around the
+/// identifier (eg. whitespace) is kept as it is.
+pub fn cycle_identifier_case(s: &str) -> String {
+    let start = match s.find(char::is_alphanumeric) {
+        Some(start) => start,
+        None => return s.to_owned(),
+    };
+    let end = s
+        .char_indices()
+        .rev()
+        .find(|(_, ch)| ch.is_alphanumeric())
+        .map_or(s.len(), |(i, ch)| i + ch.len_utf8());
+    let ident = &s[start..end];
+    let words = split_identifier(ident);
+
+    let mut case = IdentifierCase::detect(ident);
+    for _ in 0..ALL.len() {
+        case = case.next();
+        let converted = case.join(&words);
+        if converted != ident {
+            return format!("{}{}{}", &s[..start], converted, &s[end..]);
+        }
+    }
+    s.to_owned()
+}
+
+#[test]
+fn split_identifier_test() {
+    assert_eq!(
+        split_identifier("parseHTTPRequest"),
+        vec!["parse", "HTTP", "Request"]
+    );
+    assert_eq!(split_identifier("foo_bar-baz"), vec!["foo", "bar", "baz"]);
+    assert_eq!(split_identifier("Vec2d"), vec!["Vec2d"]);
+    assert_eq!(split_identifier("MAX_LEN"), vec!["MAX", "LEN"]);
+}
+
+#[test]
+fn cycle_identifier_case_test() {
+    let mut s = "foo_bar".to_owned();
+    let mut seen = vec![];
+    for _ in 0..5 {
+        s = cycle_identifier_case(&s);
+        seen.push(s.clone());
+    }
+    assert_eq!(
+        seen,
+        vec!["fooBar", "FooBar", "foo-bar", "FOO_BAR", "foo_bar"]
+    );
+
+    // single words skip the styles that don't change them
+    assert_eq!(cycle_identifier_case("foo"), "Foo");
+    assert_eq!(cycle_identifier_case(" fooBar\n"), " FooBar\n");
+    assert_eq!(swap_case("aBß"), "AbSS");
+}
+
+#[test]
+fn case_conversion_test() {
+    let mut brz = crate::headless::headless_with_text("foo_bar Baz\nqux\n");
+    brz.select(&[(0, 3), (16, 16)]);
+
+    brz.keys("~");
+    assert_eq!(brz.text(), "FOO_bar Baz\nqux\n");
+    assert_eq!(brz.selections(), vec![(0, 3), (16, 16)]);
+    brz.keys("<a-`>");
+    assert_eq!(brz.text(), "foo_bar Baz\nqux\n");
+
+    // cycles the word around the cursor
+    brz.select(&[(2, 2), (13, 13)]);
+    brz.keys("<a-~>");
+    assert_eq!(brz.text(), "fooBar Baz\nQux\n");
+    assert_eq!(brz.selections(), vec![(0, 6), (11, 14)]);
+    brz.keys("u");
+    assert_eq!(brz.text(), "foo_bar Baz\nqux\n");
+}
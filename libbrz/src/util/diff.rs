@@ -0,0 +1,140 @@
+//! Differences between two sequences (Myers' algorithm)
+
+use std::ops::Range;
+
+/// Give up looking for the shortest diff after that many edits
+const MAX_EDITS: usize = 2000;
+
+/// Changes turning `a` into `b`, in order
+///
+/// Each one replaces `a[a_range]` with `b[b_range]`. Finding the shortest
+/// diff is `O((N + M) * D)`, so with too many differences the whole part
+/// between the common prefix and suffix is replaced at once.
+pub fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(Range<usize>, Range<usize>)> {
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    if a_mid.is_empty() && b_mid.is_empty() {
+        return vec![];
+    }
+
+    let matches = match matching_pairs(a_mid, b_mid) {
+        Some(matches) => matches,
+        None => return vec![(prefix..a.len() - suffix, prefix..b.len() - suffix)],
+    };
+
+    let mut changes = vec![];
+    let (mut x, mut y) = (0, 0);
+    for (match_x, match_y) in matches
+        .into_iter()
+        .chain(std::iter::once((a_mid.len(), b_mid.len())))
+    {
+        if x < match_x || y < match_y {
+            changes.push((prefix + x..prefix + match_x, prefix + y..prefix + match_y));
+        }
+        x = match_x + 1;
+        y = match_y + 1;
+    }
+    changes
+}
+
+/// Indices of the elements of `a` and `b` kept by the shortest diff
+fn matching_pairs<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = std::cmp::min(a.len() + b.len(), MAX_EDITS) as isize;
+    let offset = max + 1;
+    // furthest `x` reached on each diagonal `k = x - y`
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // `v[-d..=d]` before each round `d`, to find the way back
+    let mut trace = vec![];
+
+    let mut found = false;
+    'rounds: for d in 0..=max {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let i = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'rounds;
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+
+    let mut matches = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let k = x - y;
+            // `v` holds diagonals `-d..=d`
+            let at = |k: isize| v[(k + d) as usize];
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            let prev_x = at(prev_k);
+            (prev_x, prev_x - prev_k)
+        };
+        // the matching elements after the edit
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    matches.reverse();
+    Some(matches)
+}
+
+#[test]
+fn diff_test() {
+    let chars = |s: &str| s.chars().collect::<Vec<_>>();
+    let apply = |a: &str, b: &str| {
+        let (a, b) = (chars(a), chars(b));
+        let mut result = a.clone();
+        for (a_range, b_range) in diff(&a, &b).into_iter().rev() {
+            result.splice(a_range, b[b_range].iter().cloned());
+        }
+        result.into_iter().collect::<String>()
+    };
+
+    assert_eq!(diff(&chars("abc"), &chars("abc")), vec![]);
+    assert_eq!(diff(&chars("abcd"), &chars("abxd")), vec![(2..3, 2..3)]);
+    assert_eq!(
+        diff(&chars("xabcyd"), &chars("abcd")),
+        vec![(0..1, 0..0), (4..5, 3..3)]
+    );
+    for &(a, b) in &[
+        ("abcabba", "cbabac"),
+        ("", "abc"),
+        ("abc", ""),
+        ("a\nb\nc\n", "a\nc\nd\n"),
+        ("xaxbx", "yaybyy"),
+    ] {
+        assert_eq!(apply(a, b), b);
+    }
+}
@@ -0,0 +1,56 @@
+//! Number literals, as `Ctrl-a`/`Ctrl-x` see them
+
+/// Add `delta` to a number literal
+///
+/// The literal is a decimal (possibly negative), `0x` hex or `0b` binary
+/// one. Hex and binary ones wrap around as `u64`, and keep their digit
+/// case. Literals with leading zeros keep their number of digits.
+pub fn add_to_literal(literal: &str, delta: i64) -> Option<String> {
+    let prefix = literal.get(..2).unwrap_or("");
+    let radix = match prefix {
+        "0x" | "0X" => 16,
+        "0b" | "0B" => 2,
+        _ => 10,
+    };
+
+    if radix == 10 {
+        let (negative, digits) = match literal.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, literal),
+        };
+        let width = if digits.starts_with('0') {
+            digits.len()
+        } else {
+            0
+        };
+        let value: i128 = digits.parse().ok()?;
+        let value = if negative { -value } else { value } + i128::from(delta);
+        let sign = if value < 0 { "-" } else { "" };
+        return Some(format!("{}{:0width$}", sign, value.abs(), width = width));
+    }
+
+    let digits = &literal[2..];
+    let value = u64::from_str_radix(digits, radix)
+        .ok()?
+        .wrapping_add(delta as u64);
+    let width = digits.len();
+    Some(match radix {
+        2 => format!("{}{:0width$b}", prefix, value, width = width),
+        _ if digits.chars().any(|ch| ch.is_ascii_uppercase()) => {
+            format!("{}{:0width$X}", prefix, value, width = width)
+        }
+        _ => format!("{}{:0width$x}", prefix, value, width = width),
+    })
+}
+
+#[test]
+fn add_to_literal_test() {
+    assert_eq!(add_to_literal("9", 1), Some("10".into()));
+    assert_eq!(add_to_literal("-1", 3), Some("2".into()));
+    assert_eq!(add_to_literal("2", -5), Some("-3".into()));
+    assert_eq!(add_to_literal("007", 1), Some("008".into()));
+    assert_eq!(add_to_literal("0xff", 1), Some("0x100".into()));
+    assert_eq!(add_to_literal("0x0A", 1), Some("0x0B".into()));
+    assert_eq!(add_to_literal("0b011", 1), Some("0b100".into()));
+    assert_eq!(add_to_literal("0x0", -1), Some("0xffffffffffffffff".into()));
+}
@@ -0,0 +1,335 @@
+use crate::render::{Coord, Rect};
+use std::cmp::{max, min};
+
+/// Smallest share of the split that a window can be resized to (percent)
+const MIN_RATIO: usize = 5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SplitDirection {
+    /// One window above the other
+    Horizontal,
+    /// Windows side by side
+    Vertical,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// A view into a buffer
+///
+/// State of the focused window lives in the `State` and the buffer itself,
+/// and is only saved here when the focus moves to another window. The
+/// selections are saved in the buffer, to follow its edits.
+#[derive(Clone, Debug, Default)]
+pub struct Window {
+    pub(crate) buffer_i: Option<usize>,
+    pub(crate) alt_buffer_i: Option<usize>,
+    pub(crate) view_line_offset: usize,
+}
+
+/// Tree of window splits
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    Window(usize),
+    Split {
+        direction: SplitDirection,
+        /// Percentage of the space taken by `first`
+        ratio: usize,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    pub fn contains(&self, window_i: usize) -> bool {
+        match self {
+            Layout::Window(i) => *i == window_i,
+            Layout::Split { first, second, .. } => {
+                first.contains(window_i) || second.contains(window_i)
+            }
+        }
+    }
+
+    pub fn first_window(&self) -> usize {
+        match self {
+            Layout::Window(i) => *i,
+            Layout::Split { first, .. } => first.first_window(),
+        }
+    }
+
+    pub fn windows(&self) -> Vec<usize> {
+        match self {
+            Layout::Window(i) => vec![*i],
+            Layout::Split { first, second, .. } => {
+                let mut windows = first.windows();
+                windows.extend(second.windows());
+                windows
+            }
+        }
+    }
+
+    /// Split `window_i`, putting `new_window_i` above or left of it
+    pub fn split(
+        &mut self,
+        window_i: usize,
+        new_window_i: usize,
+        direction: SplitDirection,
+    ) -> bool {
+        match self {
+            Layout::Window(i) if *i == window_i => {
+                *self = Layout::Split {
+                    direction,
+                    ratio: 50,
+                    first: Box::new(Layout::Window(new_window_i)),
+                    second: Box::new(Layout::Window(window_i)),
+                };
+                true
+            }
+            Layout::Window(_) => false,
+            Layout::Split { first, second, .. } => {
+                first.split(window_i, new_window_i, direction)
+                    || second.split(window_i, new_window_i, direction)
+            }
+        }
+    }
+
+    /// Remove `window_i`, giving its space to its sibling
+    ///
+    /// The last window can't be removed.
+    pub fn remove(&mut self, window_i: usize) -> bool {
+        match self {
+            Layout::Window(_) => false,
+            Layout::Split { first, second, .. } => {
+                if **first == Layout::Window(window_i) {
+                    *self = (**second).clone();
+                    true
+                } else if **second == Layout::Window(window_i) {
+                    *self = (**first).clone();
+                    true
+                } else {
+                    first.remove(window_i) || second.remove(window_i)
+                }
+            }
+        }
+    }
+
+    /// Grow (or shrink, if `delta` is negative) `window_i` by `delta` percent
+    ///
+    /// Resizes the innermost split in `direction` that the window is a part of.
+    pub fn resize(&mut self, window_i: usize, direction: SplitDirection, delta: isize) -> bool {
+        match self {
+            Layout::Window(_) => false,
+            Layout::Split {
+                direction: split_direction,
+                ratio,
+                first,
+                second,
+            } => {
+                if first.resize(window_i, direction, delta)
+                    || second.resize(window_i, direction, delta)
+                {
+                    return true;
+                }
+
+                if *split_direction != direction {
+                    return false;
+                }
+
+                let delta = if first.contains(window_i) {
+                    delta
+                } else if second.contains(window_i) {
+                    -delta
+                } else {
+                    return false;
+                };
+
+                *ratio = min(
+                    max(*ratio as isize + delta, MIN_RATIO as isize) as usize,
+                    100 - MIN_RATIO,
+                );
+                true
+            }
+        }
+    }
+
+    /// Compute the `Rect` of every window, and of the separators between them
+    pub fn window_rects(
+        &self,
+        rect: Rect,
+        windows: &mut Vec<(usize, Rect)>,
+        separators: &mut Vec<(SplitDirection, Rect)>,
+    ) {
+        match self {
+            Layout::Window(i) => windows.push((*i, rect)),
+            Layout::Split {
+                direction,
+                ratio,
+                first,
+                second,
+            } => {
+                let size = match direction {
+                    SplitDirection::Horizontal => rect.dimensions.y,
+                    SplitDirection::Vertical => rect.dimensions.x,
+                };
+
+                // too small to fit two windows and a separator
+                if size < 3 {
+                    first.window_rects(rect, windows, separators);
+                    return;
+                }
+
+                let first_size = min(max(size * ratio / 100, 1), size - 2) as isize;
+                let (first_rect, separator_rect, second_rect) = match direction {
+                    SplitDirection::Horizontal => {
+                        let (first_rect, rest) = rect.split_horizontaly_at(first_size);
+                        let (separator_rect, second_rect) = rest.split_horizontaly_at(1);
+                        (first_rect, separator_rect, second_rect)
+                    }
+                    SplitDirection::Vertical => {
+                        let (first_rect, rest) = rect.split_verticaly_at(first_size);
+                        let (separator_rect, second_rect) = rest.split_verticaly_at(1);
+                        (first_rect, separator_rect, second_rect)
+                    }
+                };
+
+                separators.push((*direction, separator_rect));
+                first.window_rects(first_rect, windows, separators);
+                second.window_rects(second_rect, windows, separators);
+            }
+        }
+    }
+}
+
+/// Find the window closest to `window_i` in the given `direction`
+pub fn window_in_direction(
+    window_rects: &[(usize, Rect)],
+    window_i: usize,
+    direction: Direction,
+) -> Option<usize> {
+    let cur = window_rects.iter().find(|(i, _)| *i == window_i)?.1;
+    let cur_end = Coord {
+        x: cur.offset.x + cur.dimensions.x,
+        y: cur.offset.y + cur.dimensions.y,
+    };
+
+    window_rects
+        .iter()
+        .filter(|(i, _)| *i != window_i)
+        .filter_map(|&(i, r)| {
+            let r_end = Coord {
+                x: r.offset.x + r.dimensions.x,
+                y: r.offset.y + r.dimensions.y,
+            };
+            let overlaps_x = r.offset.x < cur_end.x && cur.offset.x < r_end.x;
+            let overlaps_y = r.offset.y < cur_end.y && cur.offset.y < r_end.y;
+
+            // `(distance, misalignment)`
+            let (distance, misalignment) = match direction {
+                Direction::Left if overlaps_y && r_end.x <= cur.offset.x => {
+                    (cur.offset.x - r_end.x, diff(r.offset.y, cur.offset.y))
+                }
+                Direction::Right if overlaps_y && cur_end.x <= r.offset.x => {
+                    (r.offset.x - cur_end.x, diff(r.offset.y, cur.offset.y))
+                }
+                Direction::Up if overlaps_x && r_end.y <= cur.offset.y => {
+                    (cur.offset.y - r_end.y, diff(r.offset.x, cur.offset.x))
+                }
+                Direction::Down if overlaps_x && cur_end.y <= r.offset.y => {
+                    (r.offset.y - cur_end.y, diff(r.offset.x, cur.offset.x))
+                }
+                _ => return None,
+            };
+            Some((distance, misalignment, i))
+        })
+        .min()
+        .map(|(_, _, i)| i)
+}
+
+fn diff(a: usize, b: usize) -> usize {
+    max(a, b) - min(a, b)
+}
+
+#[test]
+fn layout_test() {
+    let rect = Rect {
+        offset: Coord { x: 0, y: 0 },
+        dimensions: Coord { x: 81, y: 20 },
+    };
+
+    let mut layout = Layout::Window(0);
+    assert!(layout.split(0, 1, SplitDirection::Vertical));
+    assert!(layout.split(0, 2, SplitDirection::Horizontal));
+    assert!(!layout.split(3, 4, SplitDirection::Horizontal));
+    assert_eq!(layout.windows(), vec![1, 2, 0]);
+
+    let mut windows = vec![];
+    let mut separators = vec![];
+    layout.window_rects(rect, &mut windows, &mut separators);
+    let sizes: Vec<_> = windows
+        .iter()
+        .map(|(i, r)| (*i, r.offset.x, r.offset.y, r.dimensions.x, r.dimensions.y))
+        .collect();
+    assert_eq!(
+        sizes,
+        vec![(1, 0, 0, 40, 20), (2, 41, 0, 40, 10), (0, 41, 11, 40, 9)]
+    );
+    assert_eq!(separators.len(), 2);
+
+    assert_eq!(window_in_direction(&windows, 0, Direction::Up), Some(2));
+    assert_eq!(window_in_direction(&windows, 0, Direction::Left), Some(1));
+    assert_eq!(window_in_direction(&windows, 1, Direction::Right), Some(2));
+    assert_eq!(window_in_direction(&windows, 1, Direction::Left), None);
+
+    assert!(layout.resize(1, SplitDirection::Vertical, 10));
+    assert!(!layout.resize(1, SplitDirection::Horizontal, 10));
+    assert!(layout.resize(0, SplitDirection::Horizontal, 10));
+    match &layout {
+        Layout::Split { ratio, second, .. } => {
+            assert_eq!(*ratio, 60);
+            match &**second {
+                Layout::Split { ratio, .. } => assert_eq!(*ratio, 40),
+                _ => panic!(),
+            }
+        }
+        _ => panic!(),
+    }
+
+    assert!(layout.remove(2));
+    assert!(layout.remove(1));
+    assert!(!layout.remove(0));
+    assert_eq!(layout, Layout::Window(0));
+}
+
+#[test]
+fn split_window_edit_test() {
+    let mut brz = crate::headless::headless_with_text("one\ntwo\nthree\n");
+    let cursor_line = |brz: &crate::headless::Headless| {
+        let buffer = brz.state.cur_buffer();
+        buffer.selection.selections[0]
+            .cursor
+            .to_position(&buffer.text)
+            .line
+    };
+
+    // both windows on `two`, then edit above it in the new one
+    brz.keys("j:split<ret>");
+    brz.keys("kizero<ret><esc>");
+    assert_eq!(brz.text(), "zero\none\ntwo\nthree\n");
+
+    // the other window's cursor moved down with its line
+    brz.keys("<c-w>w");
+    assert_eq!(cursor_line(&brz), 2);
+
+    // and up, after removing a line above it
+    brz.keys("kkxd");
+    assert_eq!(brz.text(), "one\ntwo\nthree\n");
+    brz.snapshot();
+    brz.keys("<c-w>w");
+    assert_eq!(cursor_line(&brz), 0);
+    assert!(brz.state.cur_buffer().selection.is_normalized());
+}